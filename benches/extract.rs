@@ -0,0 +1,99 @@
+use std::{collections::HashMap, hint::black_box, rc::Rc};
+
+use audown::{dom, js, parse_url, template::Template, Episode, EpisodeVariables};
+use criterion::{criterion_group, criterion_main, Criterion};
+use markup5ever_rcdom::{Node, NodeData};
+
+const EMBED: &str = include_str!("fixtures/embed.html");
+
+/// Inline scripts of a page, like the ones passed to the js engine.
+fn inline_script(node: Rc<Node>) -> Result<String, Rc<Node>> {
+    match node.data {
+        NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } if &*name.local == "script"
+            && !attrs.borrow().iter().any(|a| &*a.name.local == "src") =>
+        {
+            let mut text = String::new();
+            for child in node.children.borrow().iter() {
+                if let NodeData::Text { ref contents } = child.data {
+                    text.push_str(&contents.borrow());
+                }
+            }
+            Ok(text)
+        }
+        _ => Err(node),
+    }
+}
+
+fn script() -> String {
+    let mut code = String::from("const window=this||globalThis||{};");
+    for script in dom::html_filter(EMBED.as_bytes(), inline_script) {
+        code.push_str(&script);
+        code.push(';');
+    }
+    code
+}
+
+fn templates(c: &mut Criterion) {
+    let src = "{title}/{title} - {padded} [{airdate}].mp4";
+    let mut vars = HashMap::<&str, &str>::new();
+    vars.insert("title", "Sample Show");
+    vars.insert("padded", "007");
+    vars.insert("airdate", "2024-01-01");
+
+    c.bench_function("template parse", |b| {
+        b.iter(|| Template::parse(black_box(src)))
+    });
+    let template = Template::parse(src).unwrap();
+    c.bench_function("template render", |b| {
+        b.iter(|| template.render(black_box(&vars)))
+    });
+}
+
+fn dom_filter(c: &mut Criterion) {
+    c.bench_function("dom inline scripts", |b| {
+        b.iter(|| dom::html_filter(black_box(EMBED.as_bytes()), inline_script).count())
+    });
+}
+
+fn extraction(c: &mut Criterion) {
+    let code = script();
+    c.bench_function(&format!("js extract ({})", js::ENGINE), |b| {
+        b.iter(|| js::extract_video_infos(black_box(code.clone())).unwrap())
+    });
+}
+
+fn padding(c: &mut Criterion) {
+    let anime = parse_url("7").unwrap();
+    let episodes: Vec<Episode> = (1..=1000)
+        .map(|n| Episode {
+            id: n,
+            number: n.to_string(),
+            kind: None,
+            airdate: None,
+            duplicate: None,
+        })
+        .collect();
+    let template = Template::parse("{padded}").unwrap();
+
+    c.bench_function("pad format", |b| {
+        b.iter(|| {
+            for ep in &episodes {
+                black_box(format!("{:0>4}", ep.number));
+            }
+        })
+    });
+    c.bench_function("pad render", |b| {
+        b.iter(|| {
+            for ep in &episodes {
+                black_box(template.render(&EpisodeVariables::unresolved(&anime, ep).padded(4)));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, templates, dom_filter, extraction, padding);
+criterion_main!(benches);
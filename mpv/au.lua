@@ -11,7 +11,7 @@ mp.add_hook("on_load", 50, function()
 		capture_stdout = true,
 		capture_stderr = true,
 	})
-	if not res or res.error or not res.stdout then
+	if not res or res.error or res.status ~= 0 or not res.stdout then
 		return
 	end
 	local info = require("mp.utils").parse_json(res.stdout)
@@ -21,10 +21,17 @@ mp.add_hook("on_load", 50, function()
 
 	if info.type == "video" then
 		mp.set_property("stream-open-filename", info.url)
+		if info.filename then
+			mp.set_property("file-local-options/force-media-title", info.filename)
+		end
 	elseif info.type == "playlist" then
 		if info.items and #info.items > 0 then
 			for i = #info.items, 1, -1 do
-				mp.commandv("loadfile", info.items[i], "insert-next")
+				local item = info.items[i]
+				if type(item) == "table" then
+					item = item.url
+				end
+				mp.commandv("loadfile", item, "insert-next")
 			end
 		end
 		mp.commandv("playlist-remove", "current")
@@ -1,8 +1,29 @@
-use std::io::Write;
+use std::{
+    collections::BTreeMap,
+    io::{BufWriter, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    time::Duration,
+};
 
 pub use audown::*;
 
 use anyhow::{bail, Result};
+use template::Template;
+
+#[path = "../cli.rs"]
+mod cli;
+// only the defaults are used, executors are left to the main binary
+#[allow(dead_code)]
+#[path = "../config.rs"]
+mod config;
+
+const FLUSH_EVERY: usize = 64;
+const RESOLVE_JOBS: usize = 8;
+const RESOLVE_WINDOW: usize = 64;
+const DEFAULT_DEADLINE: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub struct Url<'a> {
@@ -17,10 +38,12 @@ impl<'se> serde::Serialize for Url<'se> {
         S: serde::Serializer,
     {
         serde::Serialize::serialize::<S>(
-            &format_args!(
-                "https://www.animeunity.so/anime/{}-{}/{}",
-                self.anime_id, self.slug, self.ep,
-            ),
+            &site::url(&format!(
+                "/anime/{}-{}/{}",
+                self.anime_id,
+                urlencoding::Encoded(self.slug),
+                self.ep
+            )),
             serializer,
         )
     }
@@ -34,56 +57,326 @@ fn usage() {
         "aulist"
     };
 
-    println!("USAGE: {name} <URL|ID>");
+    println!(
+        "USAGE: {name} [--resolve-all] [--filename <TEMPLATE>] [--deadline <SECS>] {} <URL|ID>",
+        cli::HTTP_USAGE
+    );
+    println!();
+    println!("OPTIONS:");
+    println!("  --resolve-all            emit direct video URLs for every playlist item");
+    println!("                           instead of page URLs; costs an embed request and");
+    println!("                           a JS evaluation per episode, {RESOLVE_JOBS} at a time;");
+    println!("                           episodes that cannot be resolved keep their page URL");
+    println!("  --filename <TEMPLATE>    add a \"filename\" field rendered from TEMPLATE");
+    println!("                           (same variables as the executors)");
+    println!("  --deadline <SECS>        give up when the whole run takes longer than SECS");
+    println!(
+        "                           ({}s by default, none with --resolve-all; 0 waits",
+        DEFAULT_DEADLINE.as_secs()
+    );
+    println!("                           forever)");
+    println!("  -h, --help               print this help");
+    println!();
+    cli::print_http_help();
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Item<T> {
+    url: T,
+    filename: Box<str>,
+}
+
+fn write_item<W: Write, T: serde::Serialize>(
+    out: &mut W,
+    url: T,
+    filename: Option<Box<str>>,
+) -> Result<()> {
+    match filename {
+        Some(filename) => serde_json::to_writer(out, &Item { url, filename })?,
+        None => serde_json::to_writer(out, &url)?,
+    }
+    Ok(())
 }
 
 fn _main() -> Result<()> {
-    let url = match std::env::args().len() {
-        2 => std::env::args().nth(1).unwrap(),
-        _ => {
-            usage();
-            std::process::exit(1);
+    let mut url = None;
+    let mut resolve_all = false;
+    let mut filename = None;
+    let mut deadline = None;
+    let mut http_args = cli::HttpArgs::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if let Some(flag) = arg.strip_prefix("--") {
+            if http_args.parse(flag, &mut args)? {
+                continue;
+            }
         }
+        match arg.as_str() {
+            "--resolve-all" => resolve_all = true,
+            "--filename" => match args.next().as_deref().map(Template::try_parse) {
+                Some(Ok(template)) => filename = Some(template),
+                Some(Err(err)) => bail!("Invalid filename template: {err}"),
+                None => {
+                    usage();
+                    std::process::exit(1);
+                }
+            },
+            "--deadline" => {
+                let secs = http::parse_secs(&cli::flag_value(&mut args, "deadline")?)?;
+                deadline = Some(Some(secs).filter(|secs| !secs.is_zero()));
+            }
+            "-h" | "--help" => {
+                usage();
+                return Ok(());
+            }
+            _ if url.is_none() && !arg.starts_with('-') => url = Some(arg),
+            _ => {
+                usage();
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let Some(url) = url else {
+        usage();
+        std::process::exit(1);
     };
+    // resolving every episode of a long series takes well over the default
+    let deadline = deadline.unwrap_or((!resolve_all).then_some(DEFAULT_DEADLINE));
+    let mut config = config::load(None)?;
+    http::configure(http_args.into_options(&mut config.defaults, false)?)?;
+    if let Some(domain) = config.defaults.domain.take() {
+        site::set_domain(domain);
+    }
+    let preference = config.defaults.title.unwrap_or_default();
 
-    let mut anime = parse_url(&url)?;
+    // a hang would freeze the player, so the work runs on its own thread
+    // and is abandoned once the deadline passes
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || tx.send(run(&url, resolve_all, filename, preference)));
+    match deadline {
+        Some(deadline) => match rx.recv_timeout(deadline) {
+            Ok(res) => res,
+            Err(RecvTimeoutError::Timeout) => {
+                bail!("Timed out after {:.1}s", deadline.as_secs_f64())
+            }
+            Err(RecvTimeoutError::Disconnected) => bail!("Aborted"),
+        },
+        None => rx.recv().map_err(|_| anyhow::anyhow!("Aborted"))?,
+    }
+}
+
+fn run(
+    url: &str,
+    resolve_all: bool,
+    filename: Option<Template>,
+    preference: TitlePreference,
+) -> Result<()> {
+    let mut anime = parse_url(url)?;
+    let reqs = filename.as_ref().map_or(Requirements::empty(), |t| {
+        Requirements::from_variables(t.variables())
+    });
+    let mut out = BufWriter::new(std::io::stdout().lock());
 
     if let Some(ep) = anime.episode {
-        let Video { url, .. } = fetch_video_infos(ep)?;
-        std::io::stdout().write_all(b"{\"type\":\"video\",\"url\":")?;
-        serde_json::to_writer(std::io::stdout(), &url)?;
-        std::io::stdout().write_all(b"}")?;
-    } else {
-        let eps = fetch_info(anime.anime_id, &mut anime.slug, &mut anime.title)
-            .map(|res| res.map(|(_, ep)| ep.id))
-            .collect::<Result<Vec<_>>>()?;
-        let Some(slug) = anime.slug.as_ref().map(|s| s.as_ref()) else {
-            bail!("Cannot find slug");
+        let video = fetch_video_infos(ep)?;
+        let filename = match filename {
+            Some(template) => {
+                let (episode, padding) = fetch_padded_episode(anime.anime_id, ep)?;
+                if let Err(err) = anime.fetch_requirements(reqs, preference) {
+                    output::warning(err);
+                }
+                Some(
+                    template
+                        .render(&EpisodeVariables::new(&anime, &video, &episode).padded(padding)),
+                )
+            }
+            None => None,
         };
-        let mut eps = eps.into_iter().map(|ep| Url {
-            anime_id: anime.anime_id,
-            slug,
-            ep,
-        });
-
-        std::io::stdout().write_all(b"{\"type\":\"playlist\",\"items\":[")?;
-        if let Some(ep) = eps.next() {
-            serde_json::to_writer(std::io::stdout(), &ep)?;
+
+        out.write_all(b"{\"type\":\"video\",\"url\":")?;
+        serde_json::to_writer(&mut out, &video.url)?;
+        if let Some(filename) = filename {
+            out.write_all(b",\"filename\":")?;
+            serde_json::to_writer(&mut out, &filename)?;
+        }
+        out.write_all(b"}")?;
+    } else if resolve_all {
+        let mut slug = anime.slug.clone();
+        let mut title = anime.title.clone();
+        let mut eps = fetch_info(anime.anime_id, &mut slug, &mut title);
+        let mut window = Vec::with_capacity(RESOLVE_WINDOW);
+        let mut written = 0usize;
+
+        out.write_all(b"{\"type\":\"playlist\",\"items\":[")?;
+        loop {
+            window.clear();
+            for ep in eps.by_ref().take(RESOLVE_WINDOW) {
+                window.push(ep?.1);
+            }
+            if window.is_empty() {
+                break;
+            }
+
+            if written == 0 {
+                anime.slug = eps.slug().map(Into::into);
+                anime.title = eps.title().map(Into::into);
+                if filename.is_some() {
+                    if let Err(err) = anime.fetch_requirements(reqs, preference) {
+                        output::warning(err);
+                    }
+                }
+            }
+
+            let padding = eps.padding();
+            write_resolved(
+                &mut out,
+                &anime,
+                &window,
+                written,
+                padding,
+                filename.as_ref(),
+            )?;
+            written += window.len();
+            out.flush()?;
         }
-        for ep in eps {
-            std::io::stdout().write_all(b",")?;
-            serde_json::to_writer(std::io::stdout(), &ep)?;
+        out.write_all(b"]}")?;
+    } else {
+        // page urls need the slug, get it before writing anything
+        if anime.slug.is_none() {
+            anime.slug = Some(resolve_slug(anime.anime_id)?);
+        }
+        let mut slug = anime.slug.clone();
+        let mut title = anime.title.clone();
+        let mut eps = fetch_info(anime.anime_id, &mut slug, &mut title);
+        let mut written = 0usize;
+
+        out.write_all(b"{\"type\":\"playlist\",\"items\":[")?;
+        while let Some(ep) = eps.next() {
+            let (_, ep) = ep?;
+
+            if written == 0 && filename.is_some() {
+                anime.slug = eps.slug().map(Into::into);
+                anime.title = eps.title().map(Into::into);
+                if let Err(err) = anime.fetch_requirements(reqs, preference) {
+                    output::warning(err);
+                }
+            }
+
+            if written != 0 {
+                out.write_all(b",")?;
+            }
+            let Some(slug) = eps.slug() else {
+                bail!(Error::SlugNotFound {
+                    anime_id: anime.anime_id
+                });
+            };
+            let name = filename.as_ref().map(|t| {
+                t.render(&EpisodeVariables::unresolved(&anime, &ep).padded(eps.padding()))
+            });
+            write_item(
+                &mut out,
+                Url {
+                    anime_id: anime.anime_id,
+                    slug,
+                    ep: ep.id,
+                },
+                name,
+            )?;
+
+            written += 1;
+            if written.is_multiple_of(FLUSH_EVERY) {
+                out.flush()?;
+            }
         }
-        std::io::stdout().write_all(b"]}")?;
+        out.write_all(b"]}")?;
     }
-    std::io::stdout().flush()?;
+    out.flush()?;
 
     Ok(())
 }
 
+/// Resolves the videos of `episodes` `RESOLVE_JOBS` at a time, writing them in
+/// order as they are ready after the `offset` items already written. An
+/// episode that cannot be resolved is written as the url of its page.
+fn write_resolved<W: Write>(
+    out: &mut W,
+    anime: &AnimeContext,
+    episodes: &[Episode],
+    offset: usize,
+    padding: usize,
+    filename: Option<&Template>,
+) -> Result<()> {
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::scope(|s| {
+        for _ in 0..RESOLVE_JOBS.min(episodes.len()) {
+            let (tx, next) = (tx.clone(), &next);
+            s.spawn(move || {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(episode) = episodes.get(i) else {
+                        break;
+                    };
+                    // the receiver is gone when writing failed
+                    if tx.send((i, fetch_video_infos(episode.id))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        let mut ready = BTreeMap::new();
+        let mut written = 0usize;
+        for (i, video) in rx {
+            ready.insert(i, video);
+            while let Some(video) = ready.remove(&written) {
+                let ep = &episodes[written];
+                if offset + written != 0 {
+                    out.write_all(b",")?;
+                }
+                match video {
+                    Ok(video) => {
+                        let name = filename.map(|t| {
+                            t.render(&EpisodeVariables::new(anime, &video, ep).padded(padding))
+                        });
+                        write_item(out, &video.url, name)?;
+                    }
+                    Err(err) => {
+                        output::warning(format_args!(
+                            "Episode {}: {err:#}, using its page url",
+                            ep.number
+                        ));
+                        let Some(slug) = anime.slug.as_deref() else {
+                            bail!(Error::SlugNotFound {
+                                anime_id: anime.anime_id
+                            });
+                        };
+                        let name = filename.map(|t| {
+                            t.render(&EpisodeVariables::unresolved(anime, ep).padded(padding))
+                        });
+                        let url = Url {
+                            anime_id: anime.anime_id,
+                            slug,
+                            ep: ep.id,
+                        };
+                        write_item(out, url, name)?;
+                    }
+                }
+
+                written += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
 fn main() {
     if let Err(err) = _main() {
-        eprintln!("{}", err);
+        output::error(err);
         std::process::exit(1);
     }
 }
@@ -0,0 +1,174 @@
+//! Command line flags shared by the binaries.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use audown::http;
+
+use crate::config;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+    /// Lists the episodes as tab-separated values instead of selecting them.
+    Tsv,
+    /// Writes an html page listing the episodes instead of selecting them.
+    Html,
+}
+
+impl std::str::FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "tsv" => Ok(Self::Tsv),
+            "html" => Ok(Self::Html),
+            _ => bail!("Invalid format {s:?} (expected text, json, tsv or html)"),
+        }
+    }
+}
+
+/// Usage of the flags parsed by [`HttpArgs`].
+pub const HTTP_USAGE: &str = "[--max-retries <N>] [--retry-delay <SECS>] [--timeout <SECS>] [--connect-timeout <SECS>] [--ipv4|--ipv6] [--accept-language <LANG>] [--proxy <URL>] [--user-agent <UA>] [--header <NAME: VALUE>]...";
+
+pub fn flag_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String> {
+    args.next()
+        .ok_or_else(|| anyhow!("Missing value for --{flag}"))
+}
+
+/// Flags configuring the http layer, each overriding the configuration.
+#[derive(Debug, Default)]
+pub struct HttpArgs {
+    pub max_retries: Option<u32>,
+    pub retry_delay: Option<Duration>,
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    pub ip_version: Option<http::IpVersion>,
+    pub accept_language: Option<String>,
+    pub proxy: Option<String>,
+    pub user_agent: Option<String>,
+    /// Added to the configured headers, replacing those with the same name.
+    pub headers: Vec<(String, String)>,
+}
+
+impl HttpArgs {
+    /// Parses `flag`, given without the leading `--`, taking its value from
+    /// `args`. Returns whether it is one of the http flags.
+    pub fn parse(&mut self, flag: &str, args: &mut impl Iterator<Item = String>) -> Result<bool> {
+        match flag {
+            "max-retries" => {
+                let value = flag_value(args, flag)?;
+                self.max_retries = Some(
+                    value
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid number of retries {value:?}"))?,
+                )
+            }
+            "retry-delay" => self.retry_delay = Some(http::parse_secs(&flag_value(args, flag)?)?),
+            "timeout" => self.timeout = Some(http::parse_secs(&flag_value(args, flag)?)?),
+            "connect-timeout" => {
+                self.connect_timeout = Some(http::parse_secs(&flag_value(args, flag)?)?)
+            }
+            "ipv4" if self.ip_version.is_none() => self.ip_version = Some(http::IpVersion::Ipv4),
+            "ipv6" if self.ip_version.is_none() => self.ip_version = Some(http::IpVersion::Ipv6),
+            "accept-language" => self.accept_language = Some(flag_value(args, flag)?),
+            "proxy" => self.proxy = Some(flag_value(args, flag)?),
+            "user-agent" => self.user_agent = Some(flag_value(args, flag)?),
+            "header" => self
+                .headers
+                .push(http::parse_header(&flag_value(args, flag)?)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// The http options given by the flags or else by `defaults`, keeping
+    /// cookies when `cookies` is set or the configuration asks for them.
+    pub fn into_options(
+        self,
+        defaults: &mut config::Defaults,
+        cookies: bool,
+    ) -> Result<http::HttpOptions> {
+        let secs = |secs: Option<f64>| {
+            secs.map(http::secs_duration)
+                .transpose()
+                .context("Error in configuration file")
+        };
+
+        let mut options = http::HttpOptions {
+            no_dns_cache: !defaults.dns_cache.unwrap_or(true),
+            max_connections: defaults.max_connections,
+            ca_bundle: defaults.ca_bundle.take(),
+            max_retries: self.max_retries.or(defaults.max_retries),
+            retry_delay: match self.retry_delay {
+                Some(delay) => Some(delay),
+                None => secs(defaults.retry_delay)?,
+            },
+            timeout: match self.timeout {
+                Some(timeout) => Some(timeout),
+                None => secs(defaults.timeout)?,
+            },
+            connect_timeout: match self.connect_timeout {
+                Some(timeout) => Some(timeout),
+                None => secs(defaults.connect_timeout)?,
+            },
+            ip_version: self.ip_version.or(defaults.ip_version).unwrap_or_default(),
+            accept_language: self.accept_language.or(defaults.accept_language.take()),
+            proxy: self.proxy.or(defaults.proxy.take()),
+            user_agent: self.user_agent.or(defaults.user_agent.take()),
+            // `--header` is added to the configured headers, replacing the same names
+            headers: defaults
+                .headers
+                .take()
+                .unwrap_or_default()
+                .into_iter()
+                .chain(self.headers)
+                .collect(),
+            ..Default::default()
+        };
+        if cookies || defaults.cookies.unwrap_or(false) {
+            let mut jar =
+                config::data_dir().ok_or_else(|| anyhow!("Cannot find data directory"))?;
+            std::fs::create_dir_all(&jar).context("Cannot create data directory")?;
+            jar.push("cookies");
+            options.cookie_jar = Some(jar);
+        }
+        Ok(options)
+    }
+}
+
+/// Describes the flags parsed by [`HttpArgs`] for `--help`.
+pub fn print_http_help() {
+    println!("HTTP OPTIONS:");
+    println!("  --max-retries <N>        retries of a request failing with a network error");
+    println!(
+        "                           or a 502, 503 or 504 status ({} by default, 0-10",
+        http::DEFAULT_RETRIES
+    );
+    println!("                           is sensible)");
+    println!("  --retry-delay <SECS>     wait before the first retry, doubled after each one");
+    println!(
+        "                           ({}s by default)",
+        http::DEFAULT_RETRY_DELAY.as_secs()
+    );
+    println!("  --timeout <SECS>         limit for each page request, downloads are only");
+    println!(
+        "                           limited while connecting ({}s by default, 0 for none)",
+        http::DEFAULT_TIMEOUT.as_secs()
+    );
+    println!(
+        "  --connect-timeout <SECS> limit for connecting ({}s by default, 0 for none)",
+        http::DEFAULT_CONNECT_TIMEOUT.as_secs()
+    );
+    println!("  --ipv4, --ipv6           connect only over IPv4 or IPv6");
+    println!("  --accept-language <LANG> Accept-Language header, empty not to send it");
+    println!("  --proxy <URL>            proxy for every request, HTTPS_PROXY or ALL_PROXY");
+    println!("                           when not given, https:// ones not with ureq");
+    println!("  --user-agent <UA>        User-Agent header, empty for the backend's own");
+    println!("  --header <NAME: VALUE>   header added to every request, can be repeated");
+}
@@ -0,0 +1,218 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::{
+    http::{self, HttpClient, HttpOptions},
+    site, AnimeContext, AnimeMetadata, Episode, InfoFetcher, Requirements, ResolvedVideos,
+    ScriptLimits, TitlePreference, Video,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    /// Site domain, [`site::DEFAULT_DOMAIN`] when unset.
+    pub domain: Option<Box<str>>,
+    pub http: HttpOptions,
+    pub title: TitlePreference,
+    pub limits: ScriptLimits,
+    /// Check video urls before returning them, falling back to other servers.
+    pub probe: bool,
+    /// Always use the player server with this name.
+    pub server: Option<Box<str>>,
+    /// How long a resolved video is reused for the same episode id,
+    /// [`DEFAULT_VIDEO_TTL`] when unset. Zero disables the cache.
+    pub video_ttl: Option<Duration>,
+}
+
+/// Resolved urls carry tokens that expire, so they are kept only briefly.
+pub const DEFAULT_VIDEO_TTL: Duration = Duration::from_secs(120);
+
+/// Bundles the options used by every fetch so they are set up once.
+///
+/// The http layer and the site domain are process wide: creating a client
+/// with [`AnimeClient::new`] configures them for the free functions and any
+/// other client, and fails if it would change the http options after the
+/// first request. [`AnimeClient::default`] leaves both as they are.
+#[derive(Debug, Clone)]
+pub struct AnimeClient {
+    title: TitlePreference,
+    limits: ScriptLimits,
+    probe: bool,
+    server: Option<Box<str>>,
+    video_ttl: Duration,
+    /// Videos resolved by [`AnimeClient::fetch_video_infos`], shared with
+    /// clones of the client.
+    videos: Arc<Mutex<HashMap<u64, (Instant, Video)>>>,
+    /// Where site pages are fetched from and videos probed,
+    /// [`http::Backend`] by default. Videos are still downloaded by the
+    /// backend.
+    http_client: Arc<dyn HttpClient>,
+}
+
+impl Default for AnimeClient {
+    fn default() -> Self {
+        Self::unconfigured(ClientOptions::default())
+    }
+}
+
+impl AnimeClient {
+    pub fn new(mut options: ClientOptions) -> Result<Self> {
+        http::configure(std::mem::take(&mut options.http))?;
+        if let Some(domain) = options.domain.take() {
+            site::set_domain(domain);
+        }
+        Ok(Self::unconfigured(options))
+    }
+
+    // `options.http` and `options.domain` are left to the caller
+    fn unconfigured(options: ClientOptions) -> Self {
+        Self {
+            title: options.title,
+            limits: options.limits,
+            probe: options.probe,
+            server: options.server,
+            video_ttl: options.video_ttl.unwrap_or(DEFAULT_VIDEO_TTL),
+            videos: Default::default(),
+            http_client: Arc::new(http::Backend),
+        }
+    }
+
+    /// Fetches site pages with `client` instead of the backend compiled in.
+    pub fn with_http_client<C: HttpClient + 'static>(mut self, client: C) -> Self {
+        self.http_client = Arc::new(client);
+        self
+    }
+
+    /// The client requests go through, the compiled-in backend unless
+    /// replaced with [`AnimeClient::with_http_client`].
+    #[inline]
+    pub fn http_client(&self) -> &dyn HttpClient {
+        &*self.http_client
+    }
+
+    #[inline]
+    pub fn title_preference(&self) -> TitlePreference {
+        self.title
+    }
+
+    #[inline]
+    pub fn script_limits(&self) -> &ScriptLimits {
+        &self.limits
+    }
+
+    #[inline]
+    pub fn parse_url(&self, url: &str) -> Result<AnimeContext> {
+        crate::parse_url(url)
+    }
+
+    /// Lists the episodes of `anime`, filling in its slug and title from the
+    /// first page when they are missing.
+    pub fn fetch_info<'a>(&self, anime: &'a mut AnimeContext) -> InfoFetcher<'a> {
+        crate::fetch_info_via(
+            Arc::clone(&self.http_client),
+            anime.anime_id,
+            &mut anime.slug,
+            &mut anime.title,
+        )
+    }
+
+    /// Every episode of `anime`, with duplicate numbers marked. Only the
+    /// info api is queried, no script is evaluated.
+    pub fn list_episodes(&self, anime: &mut AnimeContext) -> Result<Vec<Episode>> {
+        let mut episodes = self
+            .fetch_info(anime)
+            .map(|ep| ep.map(|(_, episode)| episode))
+            .collect::<Result<Vec<_>>>()?;
+        crate::mark_duplicate_numbers(&mut episodes);
+        Ok(episodes)
+    }
+
+    /// Like [`AnimeClient::fetch_info`], resolving the video of every episode
+    /// with [`AnimeClient::fetch_video_infos`].
+    pub fn resolved_videos<'a>(
+        &'a self,
+        anime: &'a mut AnimeContext,
+    ) -> ResolvedVideos<'a, impl FnMut(&Episode) -> Result<Video> + 'a> {
+        self.fetch_info(anime)
+            .resolved(move |ep| self.fetch_video_infos(ep.id))
+    }
+
+    #[inline]
+    pub fn fetch_player_info(&self, anime_id: u64, slug: &str) -> Result<serde_json::Value> {
+        crate::fetch_player_info_via(&*self.http_client, anime_id, slug)
+    }
+
+    #[inline]
+    pub fn fetch_metadata(&self, anime_id: u64, slug: &str) -> Result<AnimeMetadata> {
+        crate::fetch_metadata_via(&*self.http_client, anime_id, slug)
+    }
+
+    #[inline]
+    pub fn resolve_slug(&self, anime_id: u64) -> Result<Box<str>> {
+        crate::resolve_slug_via(&*self.http_client, anime_id)
+    }
+
+    #[inline]
+    pub fn fetch_padding(&self, anime_id: u64) -> Result<usize> {
+        crate::fetch_padding_via(&*self.http_client, anime_id)
+    }
+
+    #[inline]
+    pub fn fetch_episode(&self, anime_id: u64, episode_id: u64) -> Result<Episode> {
+        crate::fetch_episode_via(Arc::clone(&self.http_client), anime_id, episode_id)
+    }
+
+    #[inline]
+    pub fn fetch_padded_episode(&self, anime_id: u64, episode_id: u64) -> Result<(Episode, usize)> {
+        crate::fetch_padded_episode_via(Arc::clone(&self.http_client), anime_id, episode_id)
+    }
+
+    #[inline]
+    pub fn fetch_requirements(&self, anime: &mut AnimeContext, reqs: Requirements) -> Result<()> {
+        anime.fetch_requirements_via(&*self.http_client, reqs, self.title)
+    }
+
+    /// Resolves the video of episode `id`, reusing the one resolved in the
+    /// last `video_ttl`.
+    pub fn fetch_video_infos(&self, id: u64) -> Result<Video> {
+        if self.video_ttl.is_zero() {
+            return self.resolve_video(id);
+        }
+
+        {
+            let mut videos = self.videos.lock().unwrap_or_else(PoisonError::into_inner);
+            videos.retain(|_, (at, _)| at.elapsed() < self.video_ttl);
+            if let Some((_, video)) = videos.get(&id) {
+                return Ok(video.clone());
+            }
+        }
+
+        // not locked while resolving, which takes a while
+        let video = self.resolve_video(id)?;
+        self.videos
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id, (Instant::now(), video.clone()));
+        Ok(video)
+    }
+
+    fn resolve_video(&self, id: u64) -> Result<Video> {
+        let client = &*self.http_client;
+        if let Some(server) = self.server.as_deref() {
+            crate::fetch_server_video_infos_via(client, id, server, &self.limits)
+        } else if self.probe {
+            crate::fetch_live_video_infos_via(client, id, &self.limits)
+        } else {
+            crate::fetch_video_infos_via(client, id, &self.limits)
+        }
+    }
+
+    #[inline]
+    pub fn fetch_video_candidates(&self, id: u64) -> Result<Vec<Video>> {
+        crate::fetch_video_candidates_via(&*self.http_client, id, &self.limits)
+    }
+}
@@ -1,26 +1,153 @@
-use std::{collections::HashMap, fs::File, process::Command};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    time::{Duration, Instant},
+};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use directories::ProjectDirs;
 use serde::{de::Error, Deserialize};
 
-use crate::template::{Template, VarIter, Variables};
+use crate::{
+    download::{self, DownloadOptions, ExistingPolicy},
+    http, output,
+    shell::{self, ShellQuoted},
+    template::{Recording, Template, VarIter, Variables, With},
+    Seasons, TitlePreference,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub defaults: Defaults,
+    /// Season layout of anime, by id.
+    #[serde(default)]
+    pub seasons: HashMap<u64, Seasons>,
+    #[serde(flatten)]
+    pub executors: HashMap<String, Executor>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Defaults {
+    pub title: Option<TitlePreference>,
+    pub max_script_size: Option<usize>,
+    pub max_scripts: Option<usize>,
+    pub skip_irrelevant_scripts: Option<bool>,
+    pub cookies: Option<bool>,
+    pub domain: Option<Box<str>>,
+    pub probe: Option<bool>,
+    pub executor: Option<Box<str>>,
+    pub directory: Option<PathBuf>,
+    pub dns_cache: Option<bool>,
+    pub ip_version: Option<http::IpVersion>,
+    pub max_retries: Option<u32>,
+    pub retry_delay: Option<f64>,
+    pub timeout: Option<f64>,
+    pub connect_timeout: Option<f64>,
+    pub accept_language: Option<String>,
+    pub user_agent: Option<String>,
+    pub headers: Option<HashMap<String, String>>,
+    pub proxy: Option<String>,
+    pub video_ttl: Option<f64>,
+    pub stats: Option<bool>,
+    pub server: Option<Box<str>>,
+    pub specials: Option<Specials>,
+    pub include_undated: Option<bool>,
+    pub filter: Option<bool>,
+    pub format: Option<crate::cli::Format>,
+    pub require_executor: Option<bool>,
+    pub limit_rate: Option<Box<str>>,
+    pub max_connections: Option<usize>,
+    pub ca_bundle: Option<PathBuf>,
+    pub remember_selection: Option<bool>,
+    pub watched: Option<CommandExecutor>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Specials {
+    All,
+    Only,
+    Exclude,
+}
 
 #[derive(Debug)]
 pub enum Executor {
     Command(CommandExecutor),
-    Print,
+    Download(DownloadExecutor),
+    /// Prints the rendered template, or the url when there is none.
+    Print(Option<Template>),
+}
+
+impl<'de> Deserialize<'de> for Executor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Print {
+            print: String,
+        }
+
+        // templates are parsed once the variant is known, so their errors
+        // are not hidden behind the untagged enum
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Download(RawDownload),
+            Print(Print),
+            Command(RawCommand),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Download(dl) => Self::Download(dl.try_into().map_err(D::Error::custom)?),
+            Raw::Print(Print { print }) => {
+                Self::Print(Some(Template::try_parse(print).map_err(|e| {
+                    D::Error::custom(format_args!("Invalid print template: {e}"))
+                })?))
+            }
+            Raw::Command(cmd) => Self::Command(cmd.try_into().map_err(D::Error::custom)?),
+        })
+    }
+}
+
+/// Renders every variable as `{name}`, to show templates as written.
+struct Placeholders;
+
+impl Variables for Placeholders {
+    type Item<'a> = String;
+
+    #[allow(clippy::needless_lifetimes)]
+    fn get<'a, S: AsRef<str>>(&'a self, name: S) -> Option<Self::Item<'a>> {
+        Some(format!("{{{}}}", name.as_ref()))
+    }
 }
 
 pub struct CommandVariables<'a> {
     inner: Option<VarIter<'a>>,
     args: std::slice::Iter<'a, Template>,
+    post: Option<&'a CommandExecutor>,
 }
 
+type DownloadVariables<'a> = std::iter::Chain<
+    std::iter::Chain<VarIter<'a>, std::option::IntoIter<&'a str>>,
+    std::iter::Flatten<std::option::IntoIter<CommandVariables<'a>>>,
+>;
+
 #[allow(dead_code)]
 pub enum ExecutorVariables<'a> {
     Command(CommandVariables<'a>),
+    Download(DownloadVariables<'a>),
     Print(Option<&'a str>),
+    Template(VarIter<'a>),
 }
 
 impl From<CommandExecutor> for Executor {
@@ -37,10 +164,40 @@ impl<'a> From<CommandVariables<'a>> for ExecutorVariables<'a> {
 }
 
 impl Executor {
+    /// Runs the executor, warning about variables its templates use that
+    /// had no value and rendered empty.
     pub fn execute<V: Variables>(&self, values: &V) -> Result<()> {
+        let values = Recording::new(values);
+        let res = self.execute_with(&values);
+        for name in values.missing() {
+            output::warning(format_args!("Variable {{{name}}} is not available"));
+        }
+        res
+    }
+
+    /// The command the executor would run as a single line for the platform
+    /// shell, without running it. Fails for executors that run no command.
+    pub fn command_line<V: Variables>(&self, values: &V) -> Result<String> {
+        let Self::Command(cmd) = self else {
+            bail!("Only command executors can be printed as a command line");
+        };
+        let values = Recording::new(values);
+        let line = cmd.command_line(&values);
+        for name in values.missing() {
+            output::warning(format_args!("Variable {{{name}}} is not available"));
+        }
+        Ok(line)
+    }
+
+    fn execute_with<V: Variables>(&self, values: &V) -> Result<()> {
         match self {
             Self::Command(cmd) => cmd.execute(values),
-            Self::Print => {
+            Self::Download(dl) => dl.execute(values),
+            Self::Print(Some(template)) => {
+                println!("{}", template.bind(values));
+                Ok(())
+            }
+            Self::Print(None) => {
                 if let Some(url) = values.get("url") {
                     println!("{}", url);
                 } else {
@@ -51,35 +208,140 @@ impl Executor {
         }
     }
 
-    pub fn variables(&self) -> ExecutorVariables {
+    /// What the executor does, with its templates shown as written.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Command(cmd) => cmd.describe(),
+            Self::Download(dl) => {
+                let mut desc = format!("download to {}", dl.dest.render(&Placeholders));
+                if let Some(post) = &dl.post {
+                    desc.push_str(", then ");
+                    desc.push_str(&post.command.describe());
+                }
+                desc
+            }
+            Self::Print(Some(template)) => format!("print {}", template.render(&Placeholders)),
+            Self::Print(None) => "print {url}".into(),
+        }
+    }
+
+    pub fn variables(&self) -> ExecutorVariables<'_> {
         match self {
             Executor::Command(cmd) => cmd.variables().into(),
-            Executor::Print => ExecutorVariables::Print(Some("url")),
+            Executor::Download(dl) => ExecutorVariables::Download(dl.variables()),
+            Executor::Print(Some(template)) => ExecutorVariables::Template(template.variables()),
+            Executor::Print(None) => ExecutorVariables::Print(Some("url")),
         }
     }
 }
 
 #[derive(Debug, Clone)]
-pub struct CommandExecutor(Vec<Template>);
+pub struct CommandExecutor {
+    args: Vec<Template>,
+    shell: bool,
+    timeout: Option<Duration>,
+    post: Option<Post>,
+}
+
+/// A command run after an executor succeeds.
+#[derive(Debug, Clone)]
+pub struct Post {
+    command: Box<CommandExecutor>,
+    /// Whether a failure of the command fails the episode, otherwise it is
+    /// only reported.
+    fatal: bool,
+}
+
+impl Post {
+    fn new(raw: Option<Box<RawCommand>>, fatal: bool) -> Result<Option<Self>, String> {
+        let Some(raw) = raw else {
+            return Ok(None);
+        };
+        let command = CommandExecutor::try_from(*raw).map_err(|e| format!("Invalid post: {e}"))?;
+        if command.post.is_some() {
+            return Err("A post command cannot have its own post".into());
+        }
+        Ok(Some(Self {
+            command: Box::new(command),
+            fatal,
+        }))
+    }
+
+    fn run<V: Variables>(&self, values: &V) -> Result<()> {
+        let res = self.command.run(values).and_then(|status| {
+            if status.success() {
+                Ok(())
+            } else {
+                Err(anyhow!("failed with {status}"))
+            }
+        });
+        match res {
+            Err(err) if !self.fatal => {
+                output::error(format_args!("Post command: {err:#}"));
+                Ok(())
+            }
+            res => res.context("Post command"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawCommand {
+    Args(Vec<String>),
+    Full {
+        command: Vec<String>,
+        #[serde(default)]
+        shell: bool,
+        timeout: Option<f64>,
+        post: Option<Box<RawCommand>>,
+        #[serde(default)]
+        post_fatal: bool,
+    },
+}
+
+impl TryFrom<RawCommand> for CommandExecutor {
+    type Error = String;
+
+    fn try_from(raw: RawCommand) -> Result<Self, String> {
+        let (res, shell, timeout, post) = match raw {
+            RawCommand::Args(args) => (args, false, None, None),
+            RawCommand::Full {
+                command,
+                shell,
+                timeout,
+                post,
+                post_fatal,
+            } => (command, shell, timeout, Post::new(post, post_fatal)?),
+        };
+        let timeout = timeout
+            .map(|secs| Duration::try_from_secs_f64(secs).map_err(|_| "Invalid timeout"))
+            .transpose()?;
+
+        if res.is_empty() {
+            return Err("Invalid command: no arguments".into());
+        }
+        Ok(Self {
+            args: res
+                .iter()
+                .map(Template::try_parse)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("Invalid command: {e}"))?,
+            shell,
+            timeout,
+            post,
+        })
+    }
+}
 
 impl<'de> Deserialize<'de> for CommandExecutor {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        let res = <Vec<String> as Deserialize<'de>>::deserialize(deserializer)?;
-
-        if res.is_empty() {
-            Err(D::Error::custom("Invalid command"))
-        } else {
-            Ok(Self(
-                res.into_iter()
-                    .map(Template::parse)
-                    .collect::<Option<Vec<_>>>()
-                    .and_then(|vec| if vec.is_empty() { None } else { Some(vec) })
-                    .ok_or_else(|| D::Error::custom("Invalid command"))?,
-            ))
-        }
+        RawCommand::deserialize(deserializer)?
+            .try_into()
+            .map_err(D::Error::custom)
     }
 }
 
@@ -96,7 +358,13 @@ impl<'a> Iterator for CommandVariables<'a> {
                 }
             }
 
-            self.inner = Some(self.args.next()?.variables());
+            match self.args.next() {
+                Some(arg) => self.inner = Some(arg.variables()),
+                None => {
+                    let post = self.post.take()?;
+                    self.args = post.args.iter();
+                }
+            }
         }
     }
 }
@@ -108,43 +376,523 @@ impl<'a> Iterator for ExecutorVariables<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         match self {
             Self::Command(it) => it.next(),
+            Self::Download(it) => it.next(),
             Self::Print(it) => it.take(),
+            Self::Template(it) => it.next(),
         }
     }
 }
 
 impl CommandExecutor {
     pub fn execute<V: Variables>(&self, values: &V) -> Result<()> {
-        let mut cmd = Command::new(&*self.0[0].render(values));
+        let status = self.run(values)?;
+        if let Some(post) = &self.post {
+            if status.success() {
+                post.run(values)?;
+            } else {
+                output::warning(format_args!(
+                    "Command failed with {status}, skipping the post command"
+                ));
+            }
+        }
+        Ok(())
+    }
 
-        for x in self.0.iter().skip(1) {
-            cmd.arg(&*x.render(values));
+    fn run<V: Variables>(&self, values: &V) -> Result<ExitStatus> {
+        let mut child = self.command(values).spawn()?;
+        self.wait(&mut child)
+    }
+
+    /// Runs the command and returns what it printed, failing when it does
+    /// not exit successfully.
+    pub fn output<V: Variables>(&self, values: &V) -> Result<String> {
+        let mut child = self.command(values).stdout(Stdio::piped()).spawn()?;
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        // read while waiting so a full pipe cannot block the command
+        let reader = std::thread::spawn(move || {
+            let mut out = String::new();
+            stdout.read_to_string(&mut out).map(|_| out)
+        });
+
+        let status = self.wait(&mut child)?;
+        let out = reader
+            .join()
+            .map_err(|_| anyhow!("Cannot read command output"))??;
+        if !status.success() {
+            bail!("Command failed with {status}");
         }
+        Ok(out)
+    }
 
-        cmd.spawn()?.wait()?;
-        Ok(())
+    fn command<V: Variables>(&self, values: &V) -> Command {
+        if self.shell {
+            self.shell_command(values)
+        } else {
+            // literal arguments are passed as they are, without rendering
+            let mut args = self.args.iter().map(|arg| match arg.as_literal() {
+                Some(literal) => Cow::Borrowed(literal),
+                None => Cow::Owned(arg.render(values).into_string()),
+            });
+            let mut cmd = Command::new(&*args.next().unwrap_or_default());
+            for arg in args {
+                cmd.arg(&*arg);
+            }
+            cmd
+        }
+    }
+
+    fn wait(&self, child: &mut Child) -> Result<ExitStatus> {
+        let Some(timeout) = self.timeout else {
+            return Ok(child.wait()?);
+        };
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(status);
+            }
+            if Instant::now() >= deadline {
+                child.kill()?;
+                child.wait()?;
+                bail!("Command timed out after {:.1}s", timeout.as_secs_f64());
+            }
+            std::thread::sleep(POLL_INTERVAL.min(deadline - Instant::now()));
+        }
     }
 
-    pub fn variables(&self) -> CommandVariables {
+    /// Joins the arguments into a single command line for the platform
+    /// shell. Only substituted values are quoted, so the configured text can
+    /// use pipes, redirections and builtins.
+    fn shell_command<V: Variables>(&self, values: &V) -> Command {
+        let line = self.shell_line(values);
+
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+
+            let mut cmd = Command::new("cmd");
+            cmd.arg("/C").raw_arg(line);
+            cmd
+        }
+        #[cfg(not(windows))]
+        {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(line);
+            cmd
+        }
+    }
+
+    fn shell_line<V: Variables>(&self, values: &V) -> String {
+        Template::render_all(&self.args, &ShellQuoted(values)).join(" ")
+    }
+
+    /// Like the line built for `shell: true`, but quoting every argument of
+    /// the other commands. The post command is chained with `&&`.
+    fn command_line<V: Variables>(&self, values: &V) -> String {
+        let line = if self.shell {
+            self.shell_line(values)
+        } else {
+            Template::render_all(&self.args, values)
+                .iter()
+                .map(|arg| shell::quote(arg))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        match &self.post {
+            Some(post) => format!("{line} && {}", post.command.command_line(values)),
+            None => line,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut desc = Template::render_all(&self.args, &Placeholders).join(" ");
+        if self.shell {
+            desc.push_str(" (shell)");
+        }
+        if let Some(post) = &self.post {
+            desc.push_str(", then ");
+            desc.push_str(&post.command.describe());
+        }
+        desc
+    }
+
+    pub fn variables(&self) -> CommandVariables<'_> {
         CommandVariables {
             inner: None,
-            args: self.0.iter(),
+            args: self.args.iter(),
+            post: self.post.as_ref().map(|p| &*p.command),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DownloadExecutor {
+    dest: Template,
+    options: DownloadOptions,
+    post: Option<Post>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDownload {
+    download: String,
+    part_suffix: Option<String>,
+    part_dir: Option<PathBuf>,
+    #[serde(default)]
+    existing: ExistingPolicy,
+    buffer_size: Option<usize>,
+    connections: Option<usize>,
+    post: Option<Box<RawCommand>>,
+    #[serde(default)]
+    post_fatal: bool,
+}
+
+impl TryFrom<RawDownload> for DownloadExecutor {
+    type Error = String;
+
+    fn try_from(raw: RawDownload) -> Result<Self, String> {
+        let dest = Template::try_parse(&raw.download)
+            .map_err(|e| format!("Invalid download path: {e}"))?;
+        let mut options = DownloadOptions {
+            part_dir: raw.part_dir,
+            existing: raw.existing,
+            ..Default::default()
+        };
+        match raw.buffer_size {
+            Some(0) => return Err("buffer_size cannot be 0".into()),
+            Some(size) => options.buffer_size = size,
+            None => (),
+        }
+        match raw.connections {
+            Some(0) => return Err("connections cannot be 0".into()),
+            Some(n) => options.connections = n,
+            None => (),
+        }
+        if let Some(suffix) = raw.part_suffix {
+            if suffix.is_empty() {
+                return Err("part_suffix cannot be empty".into());
+            }
+            options.part_suffix = suffix.into();
+        }
+
+        Ok(Self {
+            dest,
+            options,
+            post: Post::new(raw.post, raw.post_fatal)?,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for DownloadExecutor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        RawDownload::deserialize(deserializer)?
+            .try_into()
+            .map_err(D::Error::custom)
+    }
+}
+
+impl DownloadExecutor {
+    pub fn execute<V: Variables>(&self, values: &V) -> Result<()> {
+        let url = values
+            .get("url")
+            .ok_or_else(|| anyhow!("No video url to download"))?
+            .to_string();
+        let dest = PathBuf::from(&*self.dest.render(values));
+
+        match download::download(&url, &dest, &self.options)? {
+            Some(path) => {
+                output::success(format_args!("Downloaded {}", path.display()));
+                if let Some(post) = &self.post {
+                    let output = path.to_string_lossy();
+                    post.run(&With::new(values, "output", &output))?;
+                }
+            }
+            None => output::warning(format_args!("Skipping existing {}", dest.display())),
         }
+        Ok(())
+    }
+
+    pub fn variables(&self) -> DownloadVariables<'_> {
+        self.dest.variables().chain(Some("url")).chain(
+            self.post
+                .as_ref()
+                .map(|p| p.command.variables())
+                .into_iter()
+                .flatten(),
+        )
     }
 }
 
-pub fn load() -> Result<HashMap<String, CommandExecutor>> {
-    if let Some(prj_dirs) = ProjectDirs::from("dev", "shurizzle", "AnimeUnity Downloader") {
-        let mut cfg = prj_dirs.config_dir().to_path_buf();
+/// The directory in the non-empty environment variable `var`, or the
+/// platform one.
+fn dir(var: &str, platform: fn(&ProjectDirs) -> &Path) -> Option<PathBuf> {
+    match std::env::var_os(var) {
+        Some(dir) if !dir.is_empty() => Some(dir.into()),
+        _ => ProjectDirs::from("dev", "shurizzle", "AnimeUnity Downloader")
+            .map(|prj_dirs| platform(&prj_dirs).to_path_buf()),
+    }
+}
+
+/// `AUDOWN_CONFIG_DIR`, or the platform configuration directory.
+pub fn config_dir() -> Option<PathBuf> {
+    dir("AUDOWN_CONFIG_DIR", ProjectDirs::config_dir)
+}
+
+/// `AUDOWN_DATA_DIR`, or the platform data directory.
+pub fn data_dir() -> Option<PathBuf> {
+    dir("AUDOWN_DATA_DIR", ProjectDirs::data_dir)
+}
+
+/// `AUDOWN_CACHE_DIR`, or the platform cache directory.
+#[allow(dead_code)]
+pub fn cache_dir() -> Option<PathBuf> {
+    dir("AUDOWN_CACHE_DIR", ProjectDirs::cache_dir)
+}
+
+const EXAMPLE: &str = r#"# AnimeUnity Downloader configuration
+#
+# Every top-level key other than `defaults`, `profiles` and `seasons` is an
+# executor: a command run for each selected episode, chosen with `--<name>` on
+# the command line. The `default` executor is used when none is given,
+# otherwise the direct URL of each episode is printed (the built-in `print`
+# executor). Pass `--no-default-executor` to refuse running without an
+# explicit one.
+#
+# Each argument is a template where these variables are substituted:
+#   {title} {slug} {episode} {airdate} {mal_id} {anilist_id} {file} {url}
+#   {season} {episode_in_season} (with a `seasons` entry for the anime)
+# Episodes sharing a number get a letter after it in {episode} (12a, 12b);
+# {number} is always the bare number and {padded} is {episode} with leading
+# zeros up to the width of the episode count (007).
+# Write {{ and }} for literal braces, and {"name"} for a variable whose name
+# has spaces or other characters the bare form does not allow.
+#
+# An executor can also be written as a map with `shell: true` to run the
+# arguments, joined by spaces, through `sh -c` (`cmd /C` on Windows). Only the
+# substituted values are quoted, so pipes and redirections keep working.
+# `timeout` (in seconds) kills the command if it runs for longer.
+#
+# A map with a `download` path template fetches the video directly instead of
+# running a command. The file is written as `<path><part_suffix>` (`.part` by
+# default, inside `part_dir` if set) and renamed into place once complete.
+# `existing` decides what happens when the path already exists: `skip`
+# (default), `overwrite`, or `number` to save as `name (1).ext`.
+# `buffer_size` (bytes, 65536 by default) is how much is read and buffered
+# before writing to disk. `connections` splits each file into that many
+# ranges downloaded in parallel, when the server allows it.
+#
+# Both maps accept a `post` command, run after the executor succeeds with the
+# same variables plus {output}, the downloaded file. Its failures are only
+# reported unless `post_fatal: true`.
+#
+# A map with a `print` template just prints it for each episode, e.g.
+#   list:
+#     print: "{episode}\t{title}\t{url}"
+
+download:
+  - curl
+  - "--output"
+  - "{file}"
+  - "{url}"
+
+# save:
+#   download: "{title}/{file}"
+#   existing: number
+#   part_suffix: .partial
+#   part_dir: /tmp/audown
+#   post: [notify-send, "Downloaded {output}"]
+
+# stream:
+#   shell: true
+#   timeout: 7200
+#   command:
+#     - "curl --silent {url} | mpv --force-media-title={title} -"
+
+# default:
+#   - mpv
+#   - "--force-media-title={title} - {episode}"
+#   - "{url}"
+
+# Default behavior, command line flags take precedence over these. aumpv also
+# reads the request settings (retries, timeouts, proxy, headers, cookies...),
+# `domain` and `title` from here:
+# defaults:
+#   title: english            # english, romaji or native (--title)
+#   executor: default         # executor used without --<executor>, `print` is
+#                             # the built-in one printing urls
+#   require_executor: false   # like --no-default-executor
+#   directory: /srv/anime     # where executors run and relative download paths
+#                             # are saved, created when missing
+#   server: Server1           # always use this player server (--server)
+#   probe: false              # check video urls, falling back to other servers
+#   video_ttl: 120            # seconds a resolved video is reused, 0 to disable
+#   specials: all             # all, only (--only-specials), exclude (--no-specials)
+#   include_undated: false    # keep undated episodes with --since/--until
+#   filter: true              # filter lists of over 100 episodes (--no-filter)
+#   remember_selection: false # preselect the episodes chosen last time (--remember)
+#   watched: [my-tracker, "{anilist_id}"]
+#                             # command printing the numbers of the episodes
+#                             # already watched, one per line, to leave them
+#                             # unselected (--include-watched ignores it); gets
+#                             # {anime_id} {slug} {title} {mal_id} {anilist_id}
+#   format: text              # text, json, tsv or html to list episodes (--format)
+#   stats: false              # print request statistics (--stats)
+#   cookies: false            # keep a cookie jar in the data directory (--cookies),
+#                             # where a logged in session can be copied for titles
+#                             # behind an age confirmation or login
+#   limit_rate: 2M            # cap download executors bandwidth (--limit-rate)
+#   max_connections: 4        # requests in flight at once, across all downloads
+#   dns_cache: true           # resolve each host once per run
+#   ip_version: any           # any, ipv4 (--ipv4) or ipv6 (--ipv6) connections only
+#   max_retries: 2            # retries of a request failing with a network error or
+#                             # a 502, 503 or 504 status (--max-retries)
+#   retry_delay: 1            # seconds before the first retry, doubled after each
+#                             # one (--retry-delay)
+#   timeout: 30               # seconds to fetch a page, 0 for no limit (--timeout)
+#   connect_timeout: 10       # seconds to connect, 0 for no limit (--connect-timeout)
+#   accept_language: it-IT,it;q=0.9,en;q=0.8
+#                             # Accept-Language of every request, empty to not
+#                             # send one (--accept-language); only changes what
+#                             # the site localizes, `title` still picks which of
+#                             # its titles is shown
+#   ca_bundle: /path/ca.pem   # trust these certificates instead of the default roots
+#   user_agent: Mozilla/5.0   # User-Agent of every request (--user-agent), a desktop
+#                             # Firefox by default, empty for the http backend's own
+#   headers:                  # added to every request (--header "Name: value")
+#     Referer: https://www.animeunity.so/
+#   proxy: socks5://127.0.0.1:1080
+#                             # route requests through an http://, https:// or
+#                             # socks5:// proxy (--proxy), HTTPS_PROXY or ALL_PROXY
+#                             # when unset; hosts in NO_PROXY are reached directly;
+#                             # the ureq backend cannot use https:// proxies
+#   domain: www.animeunity.so
+#   max_script_size: 4194304  # bytes of embed page script evaluated
+#   max_scripts: 64
+#   skip_irrelevant_scripts: false
+#                             # leave out embed page scripts not naming video,
+#                             # downloadUrl or streams; breaks pages setting them
+#                             # from helpers defined in another script
+#   profile: home             # profile used without --profile or AUDOWN_PROFILE
+#
+# Profiles are named sets of defaults merged over the ones above when chosen
+# with `--profile <name>` (or the AUDOWN_PROFILE environment variable):
+#
+# profiles:
+#   home:
+#     executor: mpv
+#     directory: /mnt/nas/anime
+#   laptop:
+#     executor: download
+#     directory: /home/me/Videos
+#     limit_rate: 1M
+#
+# Anime the site numbers flatly can be split into seasons, by anime id, for
+# the {season} and {episode_in_season} variables:
+#
+# seasons:
+#   1234: 1-12 S1, 13-24 S2
+"#;
+
+pub fn path() -> Option<PathBuf> {
+    config_dir().map(|mut cfg| {
         cfg.push("config.yaml");
+        cfg
+    })
+}
 
-        if cfg.exists() {
-            return serde_yml::from_reader::<_, HashMap<String, CommandExecutor>>(
-                File::open(cfg).context("Error while loading configuration")?,
-            )
-            .context("Error in configuration file");
+/// Writes an example configuration, refusing to replace an existing one.
+pub fn init() -> Result<PathBuf> {
+    let cfg = path().ok_or_else(|| anyhow!("Cannot find configuration directory"))?;
+    if let Some(dir) = cfg.parent() {
+        std::fs::create_dir_all(dir).context("Cannot create configuration directory")?;
+    }
+
+    let mut file = match File::options().write(true).create_new(true).open(&cfg) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+            bail!("{} already exists", cfg.display())
         }
+        Err(err) => return Err(err).context("Error while writing configuration"),
+    };
+    file.write_all(EXAMPLE.as_bytes())
+        .context("Error while writing configuration")?;
+
+    Ok(cfg)
+}
+
+/// Loads the configuration with the settings of `profile` merged over
+/// `defaults`. Without one, `AUDOWN_PROFILE` or `defaults.profile` names it.
+pub fn load(profile: Option<&str>) -> Result<Config> {
+    let profile = profile.map(str::to_string).or_else(|| {
+        std::env::var("AUDOWN_PROFILE")
+            .ok()
+            .filter(|p| !p.is_empty())
+    });
+
+    let Some(cfg) = path().filter(|cfg| cfg.exists()) else {
+        if let Some(name) = profile {
+            bail!("Unknown profile {name:?}: no configuration file");
+        }
+        return Ok(Config::default());
+    };
+
+    let mut value: serde_yml::Value =
+        serde_yml::from_reader(File::open(cfg).context("Error while loading configuration")?)
+            .context("Error in configuration file")?;
+    if let Some(root) = value.as_mapping_mut() {
+        apply_profile(root, profile).context("Error in configuration file")?;
+    }
+    serde_yml::from_value(value).context("Error in configuration file")
+}
+
+fn apply_profile(root: &mut serde_yml::Mapping, profile: Option<String>) -> Result<()> {
+    let profiles = match root.remove("profiles") {
+        Some(serde_yml::Value::Mapping(profiles)) => profiles,
+        Some(serde_yml::Value::Null) | None => serde_yml::Mapping::new(),
+        Some(_) => bail!("profiles must be a map"),
+    };
+
+    let defaults = root
+        .entry("defaults".into())
+        .or_insert(serde_yml::Value::Mapping(Default::default()));
+    if defaults.is_null() {
+        *defaults = serde_yml::Value::Mapping(Default::default());
     }
+    let Some(defaults) = defaults.as_mapping_mut() else {
+        bail!("defaults must be a map");
+    };
+    let default_profile = match defaults.remove("profile") {
+        Some(serde_yml::Value::String(name)) => Some(name),
+        Some(serde_yml::Value::Null) | None => None,
+        Some(_) => bail!("defaults.profile must be a string"),
+    };
 
-    Ok(HashMap::new())
+    let Some(name) = profile.or(default_profile) else {
+        return Ok(());
+    };
+    let Some(settings) = profiles.get(name.as_str()) else {
+        let mut available = profiles
+            .keys()
+            .filter_map(serde_yml::Value::as_str)
+            .collect::<Vec<_>>();
+        available.sort_unstable();
+        if available.is_empty() {
+            bail!("Unknown profile {name:?}: no profiles configured");
+        }
+        bail!(
+            "Unknown profile {name:?} (available: {})",
+            available.join(", ")
+        );
+    };
+    // checked on its own so errors name the profile
+    Defaults::deserialize(settings.clone()).with_context(|| format!("Invalid profile {name:?}"))?;
+    if let Some(settings) = settings.as_mapping() {
+        for (key, value) in settings {
+            defaults.insert(key.clone(), value.clone());
+        }
+    }
+    Ok(())
 }
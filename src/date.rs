@@ -0,0 +1,102 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A calendar date, displayed as ISO-8601 `YYYY-MM-DD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    pub fn new(year: u16, month: u8, day: u8) -> Option<Self> {
+        let days = match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if year.is_multiple_of(4)
+                && (!year.is_multiple_of(100) || year.is_multiple_of(400)) =>
+            {
+                29
+            }
+            2 => 28,
+            _ => return None,
+        };
+        (1..=days)
+            .contains(&day)
+            .then_some(Self { year, month, day })
+    }
+
+    /// Accepts the formats seen in api payloads: `YYYY-MM-DD`, optionally
+    /// followed by a time (`YYYY-MM-DD HH:MM:SS`, `YYYY-MM-DDTHH:MM:SSZ`),
+    /// `YYYY/MM/DD` and `DD/MM/YYYY`.
+    pub fn parse_lenient(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let date = s.split([' ', 'T']).next()?;
+        let mut parts = date.split(['-', '/']);
+        let (a, b, c) = (parts.next()?, parts.next()?, parts.next()?);
+        if parts.next().is_some() {
+            return None;
+        }
+
+        let (year, month, day) = if a.len() == 4 {
+            (a, b, c)
+        } else if c.len() == 4 {
+            (c, b, a)
+        } else {
+            return None;
+        };
+        Self::new(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)
+    }
+}
+
+impl FromStr for Date {
+    type Err = anyhow::Error;
+
+    /// Strict `YYYY-MM-DD`.
+    fn from_str(s: &str) -> Result<Self> {
+        let b = s.as_bytes();
+        if b.len() == 10 && b[4] == b'-' && b[7] == b'-' {
+            if let (Ok(year), Ok(month), Ok(day)) =
+                (s[..4].parse(), s[5..7].parse(), s[8..].parse())
+            {
+                if let Some(date) = Self::new(year, month, day) {
+                    return Ok(date);
+                }
+            }
+        }
+        bail!("Invalid date {s:?} (expected YYYY-MM-DD)")
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl Serialize for Date {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Date {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        Self::parse_lenient(&s).ok_or_else(|| serde::de::Error::custom("Invalid date"))
+    }
+}
+
+/// Deserializes an optional date, yielding `None` instead of failing on
+/// anything unparsable.
+pub(crate) fn deserialize_lenient<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Date>, D::Error> {
+    Ok(Option::<serde_json::Value>::deserialize(deserializer)?
+        .as_ref()
+        .and_then(serde_json::Value::as_str)
+        .and_then(Date::parse_lenient))
+}
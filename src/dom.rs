@@ -87,11 +87,21 @@ pub(crate) fn filter_tag_attr<'a>(
             ref attrs,
             ..
         } => {
-            if name.borrow().local.as_bytes() != tag.as_bytes() {
+            // html5ever lowercases names of html elements, but not of
+            // everything it treats as foreign content
+            if !name
+                .borrow()
+                .local
+                .as_bytes()
+                .eq_ignore_ascii_case(tag.as_bytes())
+            {
                 return Err(node);
             }
             if let Some(a) = attrs.take().into_iter().find(|a| {
-                a.name.local.as_bytes() == attr.as_bytes()
+                a.name
+                    .local
+                    .as_bytes()
+                    .eq_ignore_ascii_case(attr.as_bytes())
                     && !a.value.as_bytes().trim_ascii().is_empty()
             }) {
                 Ok(a.value.to_string().into_boxed_str())
@@ -104,3 +114,29 @@ pub(crate) fn filter_tag_attr<'a>(
         _ => Err(node),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixed_case_names() {
+        let body = br#"<div><Video-Player ANIME="{&quot;id&quot;:7}" Episodes_Count="12"></Video-Player></div>"#;
+        for (tag, attr) in [
+            ("video-player", "anime"),
+            ("Video-Player", "ANIME"),
+            ("VIDEO-PLAYER", "Anime"),
+        ] {
+            assert_eq!(
+                html_first(body, filter_tag_attr(tag, attr)).as_deref(),
+                Some(r#"{"id":7}"#),
+                "{tag} {attr}"
+            );
+        }
+        assert_eq!(
+            html_first(body, filter_tag_attr("video-player", "episodes_count")).as_deref(),
+            Some("12")
+        );
+        assert_eq!(html_first(body, filter_tag_attr("video", "anime")), None);
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+
+use crate::http;
+
+// smaller files are not worth splitting
+const MIN_SEGMENT: u64 = 1024 * 1024;
+
+static LIMITER: Mutex<Option<TokenBucket>> = Mutex::new(None);
+
+/// Caps the bandwidth of every following download to `bytes_per_sec`,
+/// shared by all of them. `None` removes the cap.
+pub fn set_rate_limit(bytes_per_sec: Option<u64>) {
+    *LIMITER.lock().unwrap_or_else(PoisonError::into_inner) =
+        bytes_per_sec.filter(|&r| r > 0).map(TokenBucket::new);
+}
+
+/// Parses a rate like `500K`, `2M` or `1.5G` (binary multiples, an
+/// optional trailing `B` or `/s` is ignored).
+pub fn parse_rate(s: &str) -> Result<u64> {
+    let t = s.trim();
+    let t = t.strip_suffix("/s").unwrap_or(t);
+    let t = t.strip_suffix(['B', 'b']).unwrap_or(t);
+    let (num, mult) = match t.as_bytes().last().map(u8::to_ascii_uppercase) {
+        Some(b'K') => (&t[..t.len() - 1], 1u64 << 10),
+        Some(b'M') => (&t[..t.len() - 1], 1 << 20),
+        Some(b'G') => (&t[..t.len() - 1], 1 << 30),
+        _ => (t, 1),
+    };
+    match num.trim().parse::<f64>() {
+        Ok(n) if n.is_finite() && n > 0.0 => Ok((n * mult as f64) as u64),
+        _ => bail!("Invalid rate {s:?} (expected e.g. 500K, 2M)"),
+    }
+}
+
+/// Allows up to one second worth of burst, then sleeps to stay under the
+/// rate.
+struct TokenBucket {
+    rate: u64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            tokens: rate as f64,
+            last: Instant::now(),
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Duration {
+        let now = Instant::now();
+        let rate = self.rate as f64;
+        self.tokens = (self.tokens + now.duration_since(self.last).as_secs_f64() * rate).min(rate);
+        self.last = now;
+        self.tokens -= n as f64;
+        if self.tokens < 0.0 {
+            Duration::from_secs_f64(-self.tokens / rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+struct Throttled<W>(W);
+
+impl<W: Write> Write for Throttled<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.0.write(buf)?;
+        let wait = LIMITER
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_mut()
+            .map_or(Duration::ZERO, |l| l.take(n));
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// What to do when the destination file already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExistingPolicy {
+    /// Keep the existing file and do not download.
+    #[default]
+    Skip,
+    /// Replace the existing file once the download completes.
+    Overwrite,
+    /// Download to the first free `name (N).ext`.
+    Number,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadOptions {
+    /// Appended to the file name while the download is in progress.
+    pub part_suffix: Box<str>,
+    /// Where in-progress files are written, next to the destination when
+    /// unset.
+    pub part_dir: Option<PathBuf>,
+    pub existing: ExistingPolicy,
+    /// Bytes read from the connection and buffered before each write.
+    pub buffer_size: usize,
+    /// Connections used for a single file when the server supports range
+    /// requests.
+    pub connections: usize,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            part_suffix: ".part".into(),
+            part_dir: None,
+            existing: ExistingPolicy::default(),
+            buffer_size: 64 * 1024,
+            connections: 1,
+        }
+    }
+}
+
+/// Downloads `url` to `dest` through a temporary file that is moved into
+/// place only once complete, so nothing watching the destination directory
+/// sees a partial file. Returns the final path, or `None` when an existing
+/// file was kept.
+pub fn download(url: &str, dest: &Path, options: &DownloadOptions) -> Result<Option<PathBuf>> {
+    let Some(dest) = destination(dest, options.existing) else {
+        return Ok(None);
+    };
+
+    if let Some(dir) = dest.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    }
+    if let Some(dir) = options.part_dir.as_deref() {
+        fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    }
+
+    let part = part_path(&dest, options.part_dir.as_deref(), &options.part_suffix);
+    let res = fetch(url, &part, options).and_then(|()| {
+        finalize(&part, &dest, &options.part_suffix)
+            .with_context(|| format!("Cannot move download to {}", dest.display()))
+    });
+
+    if let Err(err) = res {
+        _ = fs::remove_file(&part);
+        return Err(err);
+    }
+
+    Ok(Some(dest))
+}
+
+fn fetch(url: &str, part: &Path, options: &DownloadOptions) -> Result<()> {
+    if options.connections > 1 {
+        if let Ok(http::Resource {
+            len: Some(len),
+            ranges: true,
+        }) = http::head(url)
+        {
+            if len >= 2 * MIN_SEGMENT {
+                return fetch_segmented(url, part, len, options);
+            }
+        }
+    }
+
+    let file = File::create(part).with_context(|| format!("Cannot create {}", part.display()))?;
+    let mut out = Throttled(BufWriter::with_capacity(options.buffer_size, file));
+    http::download(url, &mut out, options.buffer_size)?;
+    out.0
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?
+        .sync_all()?;
+    Ok(())
+}
+
+/// Downloads `len` bytes as consecutive ranges in parallel, each written
+/// in place into the preallocated part file.
+fn fetch_segmented(url: &str, part: &Path, len: u64, options: &DownloadOptions) -> Result<()> {
+    File::create(part)
+        .and_then(|f| f.set_len(len))
+        .with_context(|| format!("Cannot create {}", part.display()))?;
+
+    let count = (options.connections as u64).min(len / MIN_SEGMENT);
+    let size = len.div_ceil(count);
+
+    std::thread::scope(|s| {
+        let segments = (0..count)
+            .map(|i| (i * size, ((i + 1) * size).min(len) - 1))
+            .map(|(start, end)| {
+                s.spawn(move || -> Result<()> {
+                    let mut file = File::options().write(true).open(part)?;
+                    file.seek(SeekFrom::Start(start))?;
+                    let mut out = Throttled(BufWriter::with_capacity(options.buffer_size, file));
+                    let written = http::download_range(
+                        url,
+                        Some((start, end)),
+                        &mut out,
+                        options.buffer_size,
+                    )?;
+                    if written != end - start + 1 {
+                        bail!("Incomplete segment {start}-{end}: got {written} bytes");
+                    }
+                    out.0
+                        .into_inner()
+                        .map_err(io::IntoInnerError::into_error)?
+                        .sync_all()?;
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        segments.into_iter().try_for_each(|h| {
+            h.join()
+                .unwrap_or_else(|_| Err(anyhow!("Segment download panicked")))
+        })
+    })?;
+
+    let actual = fs::metadata(part)?.len();
+    if actual != len {
+        bail!("Downloaded {actual} bytes, expected {len}");
+    }
+    Ok(())
+}
+
+fn destination(dest: &Path, policy: ExistingPolicy) -> Option<PathBuf> {
+    if !dest.exists() {
+        return Some(dest.to_path_buf());
+    }
+
+    match policy {
+        ExistingPolicy::Skip => None,
+        ExistingPolicy::Overwrite => Some(dest.to_path_buf()),
+        ExistingPolicy::Number => {
+            let stem = dest.file_stem().unwrap_or_default().to_string_lossy();
+            let ext = dest.extension().map(|e| e.to_string_lossy());
+            (1..)
+                .map_while(|n: u32| {
+                    let mut name = format!("{stem} ({n})");
+                    if let Some(ext) = ext.as_deref() {
+                        name.push('.');
+                        name.push_str(ext);
+                    }
+                    Some(dest.with_file_name(name))
+                })
+                .find(|p| !p.exists())
+        }
+    }
+}
+
+fn part_path(dest: &Path, dir: Option<&Path>, suffix: &str) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    match dir {
+        Some(dir) => dir.join(name),
+        None => dest.with_file_name(name),
+    }
+}
+
+fn finalize(part: &Path, dest: &Path, suffix: &str) -> io::Result<()> {
+    match fs::rename(part, dest) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            // copy next to the destination first so the final step is still
+            // an atomic rename
+            let tmp = part_path(dest, None, suffix);
+            let res = fs::copy(part, &tmp)
+                .and_then(|_| File::open(&tmp)?.sync_all())
+                .and_then(|()| fs::rename(&tmp, dest));
+            if res.is_err() {
+                _ = fs::remove_file(&tmp);
+            }
+            res?;
+            fs::remove_file(part)
+        }
+        res => res,
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    NoEpisodes { anime_id: u64, episodes_count: u64 },
+    TooManyScripts { max: usize },
+    ScriptTooLarge { max: usize },
+    EmbedNotFound { episode_id: u64 },
+    EpisodeNotFound { anime_id: u64, episode_id: u64 },
+    NoLiveServer { episode_id: u64 },
+    ServerNotFound { name: Box<str>, available: String },
+    SlugNotFound { anime_id: u64 },
+    AnimeNotFound { anime_id: u64 },
+    HttpStatus { status: u16, url: Box<str> },
+    InvalidTemplate { column: usize, reason: &'static str },
+    CloudflareChallenge,
+    RegionBlocked,
+    AccessRestricted,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoEpisodes {
+                anime_id,
+                episodes_count,
+            } => write!(
+                f,
+                "No episodes found for anime {anime_id} (expected {episodes_count})"
+            ),
+            Self::TooManyScripts { max } => {
+                write!(f, "Embed page has more than {max} inline scripts")
+            }
+            Self::ScriptTooLarge { max } => {
+                write!(f, "Embed page scripts exceed {max} bytes")
+            }
+            Self::EmbedNotFound { episode_id } => {
+                write!(f, "Invalid episode id {episode_id}: embed not found")
+            }
+            Self::EpisodeNotFound {
+                anime_id,
+                episode_id,
+            } => write!(f, "Episode {episode_id} is not part of anime {anime_id}"),
+            Self::NoLiveServer { episode_id } => {
+                write!(f, "No working server found for episode {episode_id}")
+            }
+            Self::SlugNotFound { anime_id } => write!(f, "Cannot find slug for anime {anime_id}"),
+            Self::AnimeNotFound { anime_id } => write!(f, "Anime {anime_id} not found"),
+            Self::HttpStatus { status, url } => write!(f, "{url}: server answered {status}"),
+            Self::InvalidTemplate { column, reason } => write!(f, "{reason} (column {column})"),
+            Self::CloudflareChallenge => write!(
+                f,
+                "Blocked by a Cloudflare challenge, retry later or through a proxy"
+            ),
+            Self::RegionBlocked => write!(
+                f,
+                "Content not available in your region, retry through a proxy"
+            ),
+            Self::AccessRestricted => write!(
+                f,
+                "Age confirmation or login required, log in with a browser and copy its session cookies into the cookie jar (--cookies)"
+            ),
+            Self::ServerNotFound { name, available } if available.is_empty() => {
+                write!(f, "Server {name:?} not found: no named servers available")
+            }
+            Self::ServerNotFound { name, available } => {
+                write!(f, "Server {name:?} not found (available: {available})")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -1,31 +1,257 @@
-use cfg_if::cfg_if;
+use std::{
+    path::{Path, PathBuf},
+    sync::{PoisonError, RwLock},
+};
 
-#[allow(unused_macros)]
-macro_rules! imp {
-    ($file:literal) => {
-        #[path = $file]
-        mod imp;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
 
-        use crate::Video;
-        use anyhow::Result;
+use crate::Video;
 
-        pub fn extract_video_infos(mut code: String) -> Result<Video> {
-            code.push_str(
-                "({file:window.video.filename||window.video.name,url:window.downloadUrl})",
+static DUMP_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+#[cfg(feature = "boa")]
+mod boa;
+#[cfg(any(feature = "quickjs", feature = "quickjs-ng"))]
+mod quickjs;
+#[cfg(feature = "v8")]
+mod v8;
+
+type Eval = fn(&str) -> Result<String>;
+
+/// Engines compiled in, in the order they are tried when a script fails:
+/// v8, boa, quickjs-ng, quickjs. `AUDOWN_JS_ENGINE` moves one to the front.
+const ENGINES: &[(&str, Eval)] = &[
+    #[cfg(feature = "v8")]
+    ("v8", v8::eval_string),
+    #[cfg(feature = "boa")]
+    ("boa", boa::eval_string),
+    #[cfg(feature = "quickjs-ng")]
+    ("quickjs-ng", quickjs::eval_string),
+    #[cfg(all(feature = "quickjs", not(feature = "quickjs-ng")))]
+    ("quickjs", quickjs::eval_string),
+];
+
+/// The engine scripts are evaluated with first, `none` in a build without
+/// engines, which can list episodes but not resolve their videos.
+pub const ENGINE: &str = match ENGINES.first() {
+    Some((name, _)) => name,
+    None => "none",
+};
+
+/// Names of the engines compiled in, in their default order.
+pub fn engines() -> impl Iterator<Item = &'static str> {
+    ENGINES.iter().map(|&(name, _)| name)
+}
+
+/// The engines to try, `AUDOWN_JS_ENGINE` first when it names one.
+fn ordered() -> Result<Vec<(&'static str, Eval)>> {
+    let mut order = ENGINES.to_vec();
+    if let Some(name) = std::env::var("AUDOWN_JS_ENGINE")
+        .ok()
+        .filter(|name| !name.is_empty())
+    {
+        let Some(i) = order.iter().position(|&(n, _)| n == name) else {
+            bail!(
+                "AUDOWN_JS_ENGINE: engine {name:?} is not compiled in (available: {})",
+                engines().collect::<Vec<_>>().join(", ")
             );
-            imp::extract_video_infos(&code)
+        };
+        let preferred = order.remove(i);
+        order.insert(0, preferred);
+    }
+    Ok(order)
+}
+
+/// Writes every script to `path` right before it is evaluated, replacing the
+/// previous one. `AUDOWN_DUMP_JS` is used when no path is set.
+pub fn set_dump_path<P: AsRef<Path>>(path: Option<P>) {
+    *DUMP_PATH.write().unwrap_or_else(PoisonError::into_inner) =
+        path.map(|p| p.as_ref().to_path_buf());
+}
+
+fn dump(code: &str) -> Result<()> {
+    let path = DUMP_PATH
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+        .or_else(|| {
+            std::env::var_os("AUDOWN_DUMP_JS")
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from)
+        });
+    if let Some(path) = path {
+        std::fs::write(&path, code)
+            .with_context(|| format!("Cannot dump script to {}", path.display()))?;
+    }
+    Ok(())
+}
+
+// the download url first, then the url of every player server
+const CANDIDATES: &str = "JSON.stringify((function(){\
+    var v=window.video||{},f=v.filename||v.name,c=[{file:f,url:window.downloadUrl}];\
+    (window.streams||[]).forEach(function(s){if(s&&s.url)c.push({file:f,url:s.url,server:s.name})});\
+    return c})())";
+
+#[derive(Deserialize)]
+struct Candidate {
+    file: Option<Box<str>>,
+    url: Option<Box<str>>,
+    server: Option<Box<str>>,
+}
+
+// engines can panic on malformed input instead of failing, keep that from
+// taking down the whole process
+fn eval_string(eval: Eval, code: &str) -> Result<String> {
+    match std::panic::catch_unwind(|| eval(code)) {
+        Ok(res) => res,
+        Err(payload) => {
+            let msg = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            bail!("JS engine panicked: {msg}")
+        }
+    }
+}
+
+/// Evaluates `code` with each engine in turn until one gives a video
+/// `accept`s, failing with the error of the last one.
+fn eval_videos(code: &str, accept: fn(&Video) -> bool) -> Result<Vec<Video>> {
+    eval_videos_with(&ordered()?, code, accept)
+}
+
+fn eval_videos_with(
+    engines: &[(&str, Eval)],
+    code: &str,
+    accept: fn(&Video) -> bool,
+) -> Result<Vec<Video>> {
+    let mut last = None;
+    for &(name, eval) in engines {
+        // an engine mishandling the script can still give valid json
+        let res = eval_string(eval, code)
+            .and_then(|json| Ok(videos(serde_json::from_str(&json)?)))
+            .and_then(|videos| {
+                if !videos.iter().any(accept) {
+                    bail!("url not found");
+                }
+                Ok(videos)
+            })
+            .with_context(|| format!("js engine {name}"));
+        match res {
+            Ok(videos) => return Ok(videos),
+            Err(err) => {
+                crate::output::debug(format_args!("{err:#}"));
+                last = Some(err);
+            }
         }
-    };
-}
-
-cfg_if! {
-    if #[cfg(feature = "v8")] {
-        imp!("v8.rs");
-    } else if #[cfg(feature = "boa")] {
-        imp!("boa.rs");
-    } else if #[cfg(any(feature = "quickjs", feature = "quickjs-ng"))] {
-        imp!("quickjs.rs");
-    } else {
-        compile_error!("No js engine selected.");
+    }
+    Err(last.unwrap_or_else(|| {
+        anyhow!("No js engine compiled in, rebuild with the boa, v8, quickjs or quickjs-ng feature")
+    }))
+}
+
+/// The candidates having both a file and a url, without repeated urls.
+fn videos(candidates: Vec<Candidate>) -> Vec<Video> {
+    let mut res: Vec<Video> = Vec::with_capacity(candidates.len());
+    for c in candidates {
+        let (Some(file), Some(url)) = (c.file, c.url) else {
+            continue;
+        };
+        if file.is_empty() || url.is_empty() || res.iter().any(|v| v.url == url) {
+            continue;
+        }
+        let server = c.server.filter(|s| !s.is_empty());
+        res.push(Video { file, url, server });
+    }
+    res
+}
+
+// the globals of an embed page read by the extraction, for `self_test`
+const SELF_TEST: &str = "const window={video:{filename:\"test.mp4\"},\
+    downloadUrl:\"https://example.com/test.mp4\"};";
+
+/// Runs a minimal extraction with each engine compiled in.
+pub fn self_test() -> Vec<(&'static str, Result<()>)> {
+    let code = format!("{SELF_TEST}{CANDIDATES}");
+    ENGINES
+        .iter()
+        .map(|&(name, eval)| {
+            let res = eval_string(eval, &code)
+                .and_then(|json| Ok(serde_json::from_str::<Vec<Candidate>>(&json)?))
+                .and_then(
+                    |candidates| match candidates.first().and_then(|c| c.url.as_deref()) {
+                        Some("https://example.com/test.mp4") => Ok(()),
+                        _ => bail!("unexpected extraction result"),
+                    },
+                );
+            (name, res)
+        })
+        .collect()
+}
+
+pub fn extract_video_candidates(mut code: String) -> Result<Vec<Video>> {
+    code.push_str(CANDIDATES);
+    dump(&code)?;
+    eval_videos(&code, |_| true)
+}
+
+/// The direct download only, player servers are left to
+/// [`extract_video_candidates`].
+pub fn extract_video_infos(mut code: String) -> Result<Video> {
+    code.push_str(CANDIDATES);
+    dump(&code)?;
+    match eval_videos(&code, |v| v.server.is_none())?
+        .into_iter()
+        .find(|v| v.server.is_none())
+    {
+        Some(video) => Ok(video),
+        None => bail!("url not found"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STREAMS: &str = "const window={video:{filename:\"test.mp4\"},\
+        streams:[{name:\"Server 1\",url:\"https://example.com/stream\"}]};";
+
+    #[test]
+    fn video_infos_without_download_url() {
+        if ENGINES.is_empty() {
+            return;
+        }
+        let candidates = extract_video_candidates(STREAMS.to_string()).unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].server.as_deref(), Some("Server 1"));
+        assert!(extract_video_infos(STREAMS.to_string()).is_err());
+
+        let code = format!("{STREAMS}window.downloadUrl=\"https://example.com/test.mp4\";");
+        let video = extract_video_infos(code).unwrap();
+        assert_eq!(&*video.url, "https://example.com/test.mp4");
+        assert!(video.server.is_none());
+    }
+
+    #[test]
+    fn engine_without_url() {
+        let engines: &[(&str, Eval)] = &[
+            ("broken", |_| Ok(r#"[{"file":"test.mp4"}]"#.into())),
+            ("streams", |_| {
+                Ok(r#"[{"file":"test.mp4","url":""},{"file":"test.mp4","url":"https://example.com/stream","server":"Server 1"}]"#.into())
+            }),
+            ("working", |_| {
+                Ok(r#"[{"file":"test.mp4","url":"https://example.com/test.mp4"}]"#.into())
+            }),
+        ];
+
+        let videos = eval_videos_with(engines, "", |_| true).unwrap();
+        assert_eq!(videos[0].server.as_deref(), Some("Server 1"));
+        let videos = eval_videos_with(engines, "", |v| v.server.is_none()).unwrap();
+        assert_eq!(&*videos[0].url, "https://example.com/test.mp4");
+
+        let err = eval_videos_with(&engines[..1], "", |_| true).unwrap_err();
+        assert_eq!(format!("{err:#}"), "js engine broken: url not found");
     }
 }
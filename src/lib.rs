@@ -1,24 +1,58 @@
+pub mod client;
+pub mod date;
 pub mod dom;
+pub mod download;
+pub mod error;
 pub mod http;
 pub mod js;
+pub mod output;
+pub mod range;
+pub mod season;
+pub mod shell;
+pub mod site;
 pub mod template;
+pub mod variables;
 
-use std::{borrow::Borrow, rc::Rc};
+use std::{borrow::Borrow, rc::Rc, sync::Arc};
 
 use anyhow::{anyhow, bail, Context, Result};
 use markup5ever_rcdom::{Node, NodeData};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use trim_in_place::TrimInPlace;
 use urlencoding::Encoded;
 
-#[derive(Debug, Clone, Deserialize)]
+use crate::http::HttpClient;
+
+pub use client::{AnimeClient, ClientOptions, DEFAULT_VIDEO_TTL};
+pub use date::Date;
+pub use error::Error;
+pub use range::EpisodeRanges;
+pub use season::Seasons;
+pub use variables::{EpisodeValue, EpisodeVariables};
+
+/// A playable video. Serializes as `{"file": ..., "url": ...}`, plus
+/// `"server"` when known.
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Video {
     pub file: Box<str>,
     pub url: Box<str>,
+    /// Name of the player server the url comes from, `None` for the direct
+    /// download.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<Box<str>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UrlSource {
+    /// A bare numeric anime id: no slug, and the anime may not exist.
+    Id,
+    /// A full animeunity URL.
+    Url,
 }
 
 #[derive(Debug)]
 pub struct AnimeContext {
+    pub source: UrlSource,
     pub anime_id: u64,
     pub slug: Option<Box<str>>,
     pub title: Option<Box<str>>,
@@ -37,46 +71,125 @@ bitflags::bitflags! {
 }
 
 impl Requirements {
+    pub fn from_variables<'a, I: IntoIterator<Item = &'a str>>(vars: I) -> Self {
+        let mut reqs = Self::empty();
+        for v in vars {
+            match v {
+                "mal_id" => reqs |= Self::MAL_ID,
+                "anilist_id" => reqs |= Self::ANILIST_ID,
+                "title" => reqs |= Self::TITLE,
+                _ => (),
+            }
+            if reqs.is_all() {
+                break;
+            }
+        }
+        reqs
+    }
+
     pub fn needs_title(&self) -> bool {
         !(*self & (Self::TITLE | Self::MAL_ID | Self::ANILIST_ID)).is_empty()
     }
 }
 
+/// Which of the titles exposed by the site ends up in [`AnimeContext::title`].
+/// The language the site localizes its pages in is asked with
+/// [`http::HttpOptions::accept_language`], this only picks among the titles
+/// it returns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TitlePreference {
+    /// `title_eng`, the English title.
+    #[default]
+    English,
+    /// `title`, the romanized Japanese title.
+    Romaji,
+    /// `title_it`, the title in the site's own language (Italian).
+    Native,
+}
+
+impl TitlePreference {
+    const ALL: [Self; 3] = [Self::English, Self::Romaji, Self::Native];
+
+    /// The preferred title first, followed by the others as fallbacks.
+    fn order(self) -> impl Iterator<Item = Self> {
+        std::iter::once(self).chain(Self::ALL.into_iter().filter(move |&p| p != self))
+    }
+}
+
+impl std::str::FromStr for TitlePreference {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "english" => Ok(Self::English),
+            "romaji" => Ok(Self::Romaji),
+            "native" => Ok(Self::Native),
+            _ => bail!("Invalid title preference {s:?} (expected english, romaji or native)"),
+        }
+    }
+}
+
+/// Everything the anime page tells about an anime, see [`fetch_metadata`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnimeMetadata {
+    #[serde(default)]
+    pub id: u64,
+    pub slug: Option<Box<str>>,
+    pub title_eng: Option<Box<str>>,
+    pub title: Option<Box<str>>,
+    pub title_it: Option<Box<str>>,
+    pub mal_id: Option<u64>,
+    pub anilist_id: Option<u64>,
+    pub episodes_count: Option<u64>,
+}
+
+impl AnimeMetadata {
+    /// The title picked by `pref`, falling back to the other ones when it is
+    /// missing or blank, as it is for entries without an English title.
+    pub fn title(&self, pref: TitlePreference) -> Option<&str> {
+        pref.order().find_map(|p| {
+            self.title_for(p)
+                .as_deref()
+                .filter(|t| !t.trim().is_empty())
+        })
+    }
+
+    fn title_for(&self, pref: TitlePreference) -> &Option<Box<str>> {
+        match pref {
+            TitlePreference::English => &self.title_eng,
+            TitlePreference::Romaji => &self.title,
+            TitlePreference::Native => &self.title_it,
+        }
+    }
+}
+
 impl AnimeContext {
-    fn fetch_title(&mut self) -> Result<()> {
-        let url = format!(
-            "https://www.animeunity.so/anime/{}-{}",
-            self.anime_id,
-            self.slug
-                .as_ref()
-                .ok_or_else(|| anyhow!("cannot find slug"))?
-        );
+    #[inline]
+    pub fn is_bare_id(&self) -> bool {
+        self.source == UrlSource::Id
+    }
 
-        let body = http::get(&url).context("Invalid informations")?;
+    fn fetch_title(&mut self, client: &dyn HttpClient, pref: TitlePreference) -> Result<()> {
+        let slug = self
+            .slug
+            .as_deref()
+            .ok_or_else(|| anyhow!("cannot find slug"))?;
 
-        if let Some(anime) = dom::html_first(
-            body.as_bytes(),
-            dom::filter_tag_attr("video-player", "anime"),
-        ) {
-            #[derive(Debug, Deserialize)]
-            struct Info {
-                pub title_eng: Box<str>,
-            }
-            let Info { title_eng: title } =
-                serde_json::from_slice(anime.as_bytes()).context("Invalid player informations")?;
-            self.title = Some(title);
+        if let Some(title) = fetch_metadata_via(client, self.anime_id, slug)?.title(pref) {
+            self.title = Some(title.into());
             return Ok(());
         }
 
         bail!("Cannot find anime title");
     }
 
-    fn fetch_ids<F>(&mut self, mut f: F) -> Result<()>
+    fn fetch_ids<F>(&mut self, client: &dyn HttpClient, mut f: F) -> Result<()>
     where
         F: FnMut(&mut AnimeContext) -> bool,
     {
-        let url = format!(
-            "https://www.animeunity.so/archivio/?title={}",
+        let path = format!(
+            "/archivio/?title={}",
             Encoded(
                 self.title
                     .as_ref()
@@ -85,7 +198,7 @@ impl AnimeContext {
             )
         );
 
-        let body = http::get(&url).context("Invalid informations")?;
+        let body = site::fetch(client, &path).context("Invalid informations")?;
 
         if let Some(anime) =
             dom::html_first(body.as_bytes(), dom::filter_tag_attr("archivio", "records"))
@@ -121,16 +234,25 @@ impl AnimeContext {
         Ok(())
     }
 
-    pub fn fetch_requirements(&mut self, reqs: Requirements) -> Result<()> {
+    pub fn fetch_requirements(&mut self, reqs: Requirements, pref: TitlePreference) -> Result<()> {
+        self.fetch_requirements_via(&http::Backend, reqs, pref)
+    }
+
+    pub(crate) fn fetch_requirements_via(
+        &mut self,
+        client: &dyn HttpClient,
+        reqs: Requirements,
+        pref: TitlePreference,
+    ) -> Result<()> {
         if reqs.needs_title() {
-            self.fetch_title()?;
+            self.fetch_title(client, pref)?;
         }
         match (
             reqs.contains(Requirements::ANILIST_ID),
             reqs.contains(Requirements::MAL_ID),
         ) {
             (true, true) => {
-                self.fetch_ids(|me| me.anilist_id.is_some() && me.mal_id.is_some())?;
+                self.fetch_ids(client, |me| me.anilist_id.is_some() && me.mal_id.is_some())?;
                 match (self.anilist_id.is_none(), self.mal_id.is_none()) {
                     (true, true) => Err(anyhow!("Cannot find anilist_id and mal_id")),
                     (false, true) => Err(anyhow!("Cannot find mal_id")),
@@ -139,7 +261,7 @@ impl AnimeContext {
                 }
             }
             (false, true) => {
-                self.fetch_ids(|me| me.mal_id.is_some())?;
+                self.fetch_ids(client, |me| me.mal_id.is_some())?;
                 if self.mal_id.is_none() {
                     Err(anyhow!("Cannot find mal_id"))
                 } else {
@@ -147,7 +269,7 @@ impl AnimeContext {
                 }
             }
             (true, false) => {
-                self.fetch_ids(|me| me.anilist_id.is_some())?;
+                self.fetch_ids(client, |me| me.anilist_id.is_some())?;
                 if self.anilist_id.is_none() {
                     Err(anyhow!("Cannot find anilist_id"))
                 } else {
@@ -162,6 +284,7 @@ impl AnimeContext {
 pub fn parse_url(url: &str) -> Result<AnimeContext> {
     if let Ok(anime_id) = url.parse::<u64>() {
         return Ok(AnimeContext {
+            source: UrlSource::Id,
             anime_id,
             slug: None,
             title: None,
@@ -189,64 +312,195 @@ pub fn parse_url(url: &str) -> Result<AnimeContext> {
     }
 
     'err: {
-        match url.path_segments() {
-            Some(mut segs) => {
-                if !matches!(segs.next(), Some("anime")) {
-                    break 'err;
-                }
-
-                let (anime_id, slug) = match segs.next() {
-                    Some(slug) => {
-                        let mut it = slug.splitn(2, '-');
-                        if let Some(id) = it.next().and_then(|id| id.parse::<u64>().ok()) {
-                            let slug = it.next().and_then(|slug| {
-                                if slug.is_empty() {
-                                    None
-                                } else {
-                                    Some(slug.into())
-                                }
-                            });
-                            (id, slug)
-                        } else {
-                            break 'err;
-                        }
-                    }
-                    None => break 'err,
-                };
+        let Some(mut segs) = url.path_segments() else {
+            break 'err;
+        };
+        // `/watch/{id}-{slug}` is an alias of `/anime/{id}-{slug}`
+        if !matches!(segs.next(), Some("anime" | "watch")) {
+            break 'err;
+        }
 
-                let episode = match segs.next() {
-                    None => None,
-                    Some(e) => {
-                        if segs.next().is_some() {
-                            break 'err;
-                        }
-                        if e.is_empty() {
+        let (anime_id, slug) = match segs.next() {
+            Some(slug) => {
+                let mut it = slug.splitn(2, '-');
+                if let Some(id) = it.next().and_then(|id| id.parse::<u64>().ok()) {
+                    // path segments stay percent-encoded, non-ASCII
+                    // slugs are decoded so they match the api ones
+                    let slug = it.next().and_then(|slug| {
+                        if slug.is_empty() {
                             None
-                        } else if let Ok(ep) = e.parse() {
-                            Some(ep)
                         } else {
-                            break 'err;
+                            Some(
+                                urlencoding::decode(slug)
+                                    .map_or_else(|_| slug.into(), |s| s.into()),
+                            )
                         }
-                    }
-                };
-
-                return Ok(AnimeContext {
-                    anime_id,
-                    slug,
-                    title: None,
-                    episode,
-                    mal_id: None,
-                    anilist_id: None,
-                });
+                    });
+                    (id, slug)
+                } else {
+                    break 'err;
+                }
             }
             None => break 'err,
+        };
+
+        // the episode id is either the next segment, optionally after a
+        // `watch` one, or an `episode`/`episode_id` query parameter
+        let mut segs = segs.filter(|s| !s.is_empty());
+        let episode = match segs.next() {
+            Some("watch") => segs.next(),
+            e => e,
+        };
+        if segs.next().is_some() {
+            break 'err;
         }
+        let query = url
+            .query_pairs()
+            .find(|(k, _)| k == "episode" || k == "episode_id")
+            .map(|(_, v)| v);
+        let episode = match episode.or(query.as_deref()) {
+            None => None,
+            Some(e) => match e.parse() {
+                Ok(ep) => Some(ep),
+                Err(_) => break 'err,
+            },
+        };
+
+        return Ok(AnimeContext {
+            source: UrlSource::Url,
+            anime_id,
+            slug,
+            title: None,
+            episode,
+            mal_id: None,
+            anilist_id: None,
+        });
     }
 
-    bail!("Invalid path")
+    bail!(
+        "Invalid path {:?} (expected /anime/<id>-<slug>[/<episode id>])",
+        url.path()
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptLimits {
+    pub max_size: usize,
+    pub max_scripts: usize,
+    /// Leave out the scripts never naming the globals the extraction reads,
+    /// breaking pages that set them from helpers defined elsewhere.
+    pub skip_irrelevant: bool,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        Self {
+            max_size: 4 * 1024 * 1024,
+            max_scripts: 64,
+            skip_irrelevant: false,
+        }
+    }
 }
 
 pub fn fetch_video_infos(id: u64) -> Result<Video> {
+    fetch_video_infos_with(id, &ScriptLimits::default())
+}
+
+pub fn fetch_video_infos_with(id: u64, limits: &ScriptLimits) -> Result<Video> {
+    fetch_video_infos_via(&http::Backend, id, limits)
+}
+
+pub(crate) fn fetch_video_infos_via(
+    client: &dyn HttpClient,
+    id: u64,
+    limits: &ScriptLimits,
+) -> Result<Video> {
+    js::extract_video_infos(assemble_script(&fetch_embed_page(client, id)?, limits)?)
+}
+
+/// Returns every playable source found in the embed page, the direct
+/// download first followed by the other player servers.
+pub fn fetch_video_candidates(id: u64) -> Result<Vec<Video>> {
+    fetch_video_candidates_with(id, &ScriptLimits::default())
+}
+
+pub fn fetch_video_candidates_with(id: u64, limits: &ScriptLimits) -> Result<Vec<Video>> {
+    fetch_video_candidates_via(&http::Backend, id, limits)
+}
+
+pub(crate) fn fetch_video_candidates_via(
+    client: &dyn HttpClient,
+    id: u64,
+    limits: &ScriptLimits,
+) -> Result<Vec<Video>> {
+    js::extract_video_candidates(assemble_script(&fetch_embed_page(client, id)?, limits)?)
+}
+
+// resolving a long list of episodes makes many requests, one failing should
+// not end it
+fn fetch_embed_page(client: &dyn HttpClient, id: u64) -> Result<String> {
+    let url = http::retry(|| fetch_embed_url(client, id))?;
+    http::retry(|| client.get(&url))
+}
+
+/// Like [`fetch_video_infos_with`], but checks that the url is still alive
+/// and falls back to the next server when it is not. Costs one more request
+/// per server tried.
+pub fn fetch_live_video_infos_with(id: u64, limits: &ScriptLimits) -> Result<Video> {
+    fetch_live_video_infos_via(&http::Backend, id, limits)
+}
+
+pub(crate) fn fetch_live_video_infos_via(
+    client: &dyn HttpClient,
+    id: u64,
+    limits: &ScriptLimits,
+) -> Result<Video> {
+    for video in fetch_video_candidates_via(client, id, limits)? {
+        match client.probe(&video.url) {
+            Ok(true) => {
+                output::debug(format_args!("Episode {id}: using {}", video.url));
+                return Ok(video);
+            }
+            Ok(false) => output::debug(format_args!("Episode {id}: {} is dead", video.url)),
+            Err(err) => output::debug(format_args!("Episode {id}: {} failed: {err}", video.url)),
+        }
+    }
+
+    bail!(Error::NoLiveServer { episode_id: id })
+}
+
+/// Picks the video of the player server called `name` (case-insensitive).
+pub fn fetch_server_video_infos_with(id: u64, name: &str, limits: &ScriptLimits) -> Result<Video> {
+    fetch_server_video_infos_via(&http::Backend, id, name, limits)
+}
+
+pub(crate) fn fetch_server_video_infos_via(
+    client: &dyn HttpClient,
+    id: u64,
+    name: &str,
+    limits: &ScriptLimits,
+) -> Result<Video> {
+    let candidates = fetch_video_candidates_via(client, id, limits)?;
+    let available = candidates
+        .iter()
+        .filter_map(|v| v.server.as_deref())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    match candidates.into_iter().find(|v| {
+        v.server
+            .as_deref()
+            .is_some_and(|s| s.eq_ignore_ascii_case(name))
+    }) {
+        Some(video) => Ok(video),
+        None => bail!(Error::ServerNotFound {
+            name: name.into(),
+            available,
+        }),
+    }
+}
+
+fn assemble_script(body: &str, limits: &ScriptLimits) -> Result<String> {
     fn filter_script(node: Rc<Node>) -> Result<String, Rc<Node>> {
         match node.data {
             NodeData::Element {
@@ -270,27 +524,76 @@ pub fn fetch_video_infos(id: u64) -> Result<Video> {
         }
     }
 
-    js::extract_video_infos(
-        dom::html_filter(http::get(&fetch_embed_url(id)?)?.as_bytes(), filter_script)
-            .map(|mut s| {
-                s.trim_in_place();
-                s
-            })
-            .filter(|s| !s.is_empty())
-            .fold(
-                String::from("const window=this||globalThis||{};"),
-                |mut code, script| {
-                    code.push_str("try{");
-                    code.push_str(&script);
-                    code.push_str("}catch(____e){}\n");
-                    code
-                },
-            ),
-    )
+    // scripts never mentioning the globals read by the extraction, they can
+    // still define what a later script assigns to them
+    fn is_relevant(script: &str) -> bool {
+        script.contains("video") || script.contains("downloadUrl") || script.contains("streams")
+    }
+
+    let mut code = String::from("const window=this||globalThis||{};");
+    let mut count = 0;
+
+    for script in dom::html_filter(body.as_bytes(), filter_script)
+        .map(|mut s| {
+            s.trim_in_place();
+            s
+        })
+        .filter(|s| !s.is_empty() && (!limits.skip_irrelevant || is_relevant(s)))
+    {
+        count += 1;
+        if count > limits.max_scripts {
+            bail!(Error::TooManyScripts {
+                max: limits.max_scripts
+            });
+        }
+
+        code.push_str("try{");
+        code.push_str(&script);
+        code.push_str("}catch(____e){}\n");
+
+        if code.len() > limits.max_size {
+            bail!(Error::ScriptTooLarge {
+                max: limits.max_size
+            });
+        }
+    }
+
+    Ok(code)
+}
+
+fn fetch_embed_url(client: &dyn HttpClient, id: u64) -> Result<String> {
+    let body = site::fetch(client, &format!("/embed-url/{id}"))?;
+    parse_embed_url(id, &body).map_err(|err| match gate(&body) {
+        Some(gate) => gate.into(),
+        None => err,
+    })
+}
+
+/// Recognizes an age confirmation or login wall served in place of a page,
+/// only checked once the expected content is missing since the login form
+/// can be part of every page.
+fn gate(body: &str) -> Option<Error> {
+    const MARKERS: &[&str] = &[
+        "age-verification",
+        "age_verification",
+        "age-gate",
+        "maggiorenne",
+        "name=\"password\"",
+    ];
+
+    MARKERS
+        .iter()
+        .any(|m| body.contains(m))
+        .then_some(Error::AccessRestricted)
 }
 
-fn fetch_embed_url(id: u64) -> Result<String> {
-    http::get(&format!("https://www.animeunity.so/embed-url/{id}"))
+fn parse_embed_url(id: u64, body: &str) -> Result<String> {
+    match url::Url::parse(body.trim()) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.host().is_some() => {
+            Ok(url.into())
+        }
+        _ => bail!(Error::EmbedNotFound { episode_id: id }),
+    }
 }
 
 fn extract_text(node: Rc<Node>) -> String {
@@ -311,10 +614,139 @@ fn extract_text(node: Rc<Node>) -> String {
     acc
 }
 
-#[derive(Debug, Deserialize)]
+/// An episode as listed by the site. Serializes as `{"id": ..., "number": ...}`,
+/// the same shape the info api returns.
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Episode {
     pub id: u64,
     pub number: String,
+    /// Category reported by the api, when there is one.
+    #[serde(
+        rename = "type",
+        alias = "category",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub kind: Option<String>,
+    /// When the episode aired or was published on the site.
+    #[serde(
+        alias = "air_date",
+        alias = "created_at",
+        default,
+        deserialize_with = "date::deserialize_lenient",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub airdate: Option<Date>,
+    /// Suffix telling this episode apart from others with the same `number`,
+    /// see [`mark_duplicate_numbers`].
+    #[serde(skip)]
+    pub duplicate: Option<String>,
+}
+
+/// Gives the episodes sharing a `number` (re-airs, alternative versions) a
+/// suffix each, in listing order, so they can be told apart: `a` to `z`, then
+/// `aa`, `ab` and so on.
+pub fn mark_duplicate_numbers(episodes: &mut [Episode]) {
+    let mut counts = std::collections::HashMap::<String, u32>::new();
+    for ep in episodes.iter() {
+        *counts.entry(ep.number.trim().to_string()).or_default() += 1;
+    }
+
+    let mut next = std::collections::HashMap::<String, usize>::new();
+    for ep in episodes.iter_mut() {
+        if counts[ep.number.trim()] > 1 {
+            let n = next.entry(ep.number.trim().to_string()).or_default();
+            ep.duplicate = Some(duplicate_suffix(*n));
+            *n += 1;
+        }
+    }
+}
+
+/// The `n`th (zero based) suffix in the sequence `a`..`z`, `aa`..`zz`, `aaa`..
+fn duplicate_suffix(mut n: usize) -> String {
+    let mut suffix = Vec::new();
+    loop {
+        suffix.push(b'a' + (n % 26) as u8);
+        if n < 26 {
+            break;
+        }
+        n = n / 26 - 1;
+    }
+    suffix.reverse();
+    String::from_utf8(suffix).unwrap()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EpisodeKind {
+    Regular,
+    /// OVAs, movies, recaps and half episodes.
+    Special,
+}
+
+impl Episode {
+    /// Uses the api category when present, otherwise anything numbered
+    /// other than a plain integer is a special.
+    pub fn kind(&self) -> EpisodeKind {
+        if let Some(kind) = self.kind.as_deref().map(str::trim) {
+            if !kind.is_empty() {
+                return if ["episode", "tv", "regular"]
+                    .iter()
+                    .any(|k| kind.eq_ignore_ascii_case(k))
+                {
+                    EpisodeKind::Regular
+                } else {
+                    EpisodeKind::Special
+                };
+            }
+        }
+
+        let number = self.number.trim();
+        if !number.is_empty() && number.bytes().all(|c| c.is_ascii_digit()) {
+            EpisodeKind::Regular
+        } else {
+            EpisodeKind::Special
+        }
+    }
+
+    /// `number` followed by the [`duplicate`](Episode::duplicate) suffix, if
+    /// any.
+    pub fn label(&self) -> std::borrow::Cow<'_, str> {
+        match self.duplicate {
+            Some(ref c) => format!("{}{c}", self.number).into(),
+            None => (&*self.number).into(),
+        }
+    }
+
+    /// Numeric value of `number`, ignoring any trailing suffix: `"7"` is
+    /// `7.0`, `"7.5"` and `"7,5"` are `7.5`, `"12a"` is `12.0`.
+    pub fn number_f64(&self) -> Option<f64> {
+        let number = self.number.trim();
+        let int_len = number
+            .bytes()
+            .position(|c| !c.is_ascii_digit())
+            .unwrap_or(number.len());
+        if int_len == 0 {
+            return None;
+        }
+
+        let (int, rest) = number.split_at(int_len);
+        let frac = rest
+            .strip_prefix(['.', ','])
+            .map(|rest| {
+                let len = rest
+                    .bytes()
+                    .position(|c| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                &rest[..len]
+            })
+            .unwrap_or_default();
+
+        if frac.is_empty() {
+            int.parse().ok()
+        } else {
+            format!("{int}.{frac}").parse().ok()
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -322,281 +754,1233 @@ pub struct Info {
     pub slug: Option<Box<str>>,
     pub title: Option<Box<str>>,
     pub episodes_count: u64,
+    #[serde(default)]
     pub episodes: Vec<Episode>,
 }
 
-pub fn fetch_info<'a>(
-    id: u64,
-    slug: &'a mut Option<Box<str>>,
-    title: &'a mut Option<Box<str>>,
-) -> impl Iterator<Item = Result<(Box<str>, Episode)>> + 'a {
-    #[derive(Deserialize)]
-    pub struct InfoMin {
-        pub episodes_count: u64,
-        pub episodes: Vec<Episode>,
-    }
-
-    #[derive(Deserialize)]
-    pub struct InfoSlug {
-        pub slug: Option<Box<str>>,
-        pub episodes_count: u64,
-        pub episodes: Vec<Episode>,
-    }
-
-    #[derive(Deserialize)]
-    pub struct InfoTitle {
-        pub name: Option<Box<str>>,
-        pub episodes_count: u64,
-        pub episodes: Vec<Episode>,
-    }
-
-    #[derive(Deserialize)]
-    pub struct InfoSlugTitle {
-        pub name: Option<Box<str>>,
-        pub slug: Option<Box<str>>,
-        pub episodes_count: u64,
-        pub episodes: Vec<Episode>,
-    }
-
-    impl From<InfoMin> for Info {
-        fn from(
-            InfoMin {
-                episodes_count,
-                episodes,
-            }: InfoMin,
-        ) -> Self {
-            Info {
-                slug: None,
-                title: None,
-                episodes_count,
-                episodes,
-            }
+#[derive(Deserialize)]
+struct InfoMin {
+    pub episodes_count: u64,
+    #[serde(default)]
+    pub episodes: Vec<Episode>,
+}
+
+#[derive(Deserialize)]
+struct InfoSlug {
+    pub slug: Option<Box<str>>,
+    pub episodes_count: u64,
+    #[serde(default)]
+    pub episodes: Vec<Episode>,
+}
+
+#[derive(Deserialize)]
+struct InfoTitle {
+    pub name: Option<Box<str>>,
+    pub episodes_count: u64,
+    #[serde(default)]
+    pub episodes: Vec<Episode>,
+}
+
+#[derive(Deserialize)]
+struct InfoSlugTitle {
+    pub name: Option<Box<str>>,
+    pub slug: Option<Box<str>>,
+    pub episodes_count: u64,
+    #[serde(default)]
+    pub episodes: Vec<Episode>,
+}
+
+impl From<InfoMin> for Info {
+    fn from(
+        InfoMin {
+            episodes_count,
+            episodes,
+        }: InfoMin,
+    ) -> Self {
+        Info {
+            slug: None,
+            title: None,
+            episodes_count,
+            episodes,
         }
     }
+}
 
-    impl From<InfoSlug> for Info {
-        fn from(
-            InfoSlug {
-                slug,
-                episodes_count,
-                episodes,
-            }: InfoSlug,
-        ) -> Self {
-            Info {
-                slug,
-                title: None,
-                episodes_count,
-                episodes,
-            }
+impl From<InfoSlug> for Info {
+    fn from(
+        InfoSlug {
+            slug,
+            episodes_count,
+            episodes,
+        }: InfoSlug,
+    ) -> Self {
+        Info {
+            slug,
+            title: None,
+            episodes_count,
+            episodes,
         }
     }
+}
 
-    impl From<InfoTitle> for Info {
-        fn from(
-            InfoTitle {
-                name,
-                episodes_count,
-                episodes,
-            }: InfoTitle,
-        ) -> Self {
-            Info {
-                slug: None,
-                title: name,
-                episodes_count,
-                episodes,
-            }
+impl From<InfoTitle> for Info {
+    fn from(
+        InfoTitle {
+            name,
+            episodes_count,
+            episodes,
+        }: InfoTitle,
+    ) -> Self {
+        Info {
+            slug: None,
+            title: name,
+            episodes_count,
+            episodes,
         }
     }
+}
 
-    impl From<InfoSlugTitle> for Info {
-        fn from(
-            InfoSlugTitle {
-                slug,
-                name,
-                episodes_count,
-                episodes,
-            }: InfoSlugTitle,
-        ) -> Self {
-            Info {
-                slug,
-                title: name,
-                episodes_count,
-                episodes,
-            }
+impl From<InfoSlugTitle> for Info {
+    fn from(
+        InfoSlugTitle {
+            slug,
+            name,
+            episodes_count,
+            episodes,
+        }: InfoSlugTitle,
+    ) -> Self {
+        Info {
+            slug,
+            title: name,
+            episodes_count,
+            episodes,
         }
     }
+}
 
-    fn parse_info<'a, T: Into<Info> + Deserialize<'a>>(body: &'a str) -> serde_json::Result<Info> {
-        serde_json::from_slice::<T>(body.as_bytes()).map(Into::into)
-    }
+fn parse_info<'a, T: Into<Info> + Deserialize<'a>>(body: &'a str) -> serde_json::Result<Info> {
+    serde_json::from_slice::<T>(body.as_bytes()).map(Into::into)
+}
 
-    fn fetch_info_page<'a>(
-        id: u64,
-        start: u64,
-        stop: u64,
-        slug: &'a mut Option<Box<str>>,
-        title: &'a mut Option<Box<str>>,
-    ) -> Result<Info> {
-        let url = format!(
-            "https://www.animeunity.so/info_api/{}/1?start_range={}&end_range={}",
-            id, start, stop
-        );
+/// Path of an `info_api` request for the episodes `start..=end`, kept in
+/// one place in case the site changes its pagination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InfoApiPath {
+    pub anime_id: u64,
+    pub page: u64,
+    pub start: u64,
+    pub end: u64,
+}
 
-        let body = http::get(&url).context("Invalid informations")?;
+impl InfoApiPath {
+    /// Episodes requested per page.
+    pub const PAGE_SIZE: u64 = 120;
 
-        match (slug.is_none(), title.is_none()) {
-            (true, true) => parse_info::<InfoSlugTitle>(&body),
-            (true, false) => parse_info::<InfoSlug>(&body),
-            (false, true) => parse_info::<InfoTitle>(&body),
-            (false, false) => parse_info::<InfoMin>(&body),
+    #[inline]
+    pub fn new(anime_id: u64, start: u64, end: u64) -> Self {
+        Self {
+            anime_id,
+            page: 1,
+            start,
+            end,
         }
-        .context("Invalid informations")
     }
+}
 
-    fn num_len(mut n: u64) -> usize {
-        if n == 0 {
-            return 1;
-        }
+impl std::fmt::Display for InfoApiPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "/info_api/{}/{}?start_range={}&end_range={}",
+            self.anime_id, self.page, self.start, self.end
+        )
+    }
+}
 
-        let mut len = 0;
-        while n > 0 {
-            n /= 10;
-            len += 1;
-        }
-        len
+fn fetch_info_page<'a>(
+    client: &dyn HttpClient,
+    id: u64,
+    start: u64,
+    stop: u64,
+    slug: &'a mut Option<Box<str>>,
+    title: &'a mut Option<Box<str>>,
+) -> Result<Info> {
+    let path = InfoApiPath::new(id, start, stop).to_string();
+
+    // a long archive takes many pages, one failing should not end the listing
+    let body = http::retry(|| site::fetch(client, &path)).context("Invalid informations")?;
+
+    match (slug.is_none(), title.is_none()) {
+        (true, true) => parse_info::<InfoSlugTitle>(&body),
+        (true, false) => parse_info::<InfoSlug>(&body),
+        (false, true) => parse_info::<InfoTitle>(&body),
+        (false, false) => parse_info::<InfoMin>(&body),
     }
+    .context("Invalid informations")
+}
+
+fn num_len(mut n: u64) -> usize {
+    if n == 0 {
+        return 1;
+    }
+
+    let mut len = 0;
+    while n > 0 {
+        n /= 10;
+        len += 1;
+    }
+    len
+}
+
+struct Pages {
+    current: u64,
+    max: u64,
+}
 
-    struct Pages {
-        current: u64,
-        max: u64,
+impl Pages {
+    #[inline(always)]
+    pub fn new(max: u64) -> Self {
+        Self { current: 1, max }
     }
 
-    impl Pages {
-        #[inline(always)]
-        pub fn new(max: u64) -> Self {
-            Self { current: 1, max }
+    /// Adds a page after the last one, for episodes past the announced count.
+    fn extend(&mut self) {
+        self.max = self.current - 1 + InfoApiPath::PAGE_SIZE;
+    }
+}
+
+impl Iterator for Pages {
+    type Item = (u64, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current <= self.max {
+            let start = self.current;
+            self.current += InfoApiPath::PAGE_SIZE;
+            let stop = (self.current - 1).min(self.max);
+            Some((start, stop))
+        } else {
+            None
         }
     }
+}
+
+pub struct InfoFetcher<'a> {
+    client: Arc<dyn HttpClient>,
+    id: u64,
+    num_len: usize,
+    episodes_count: u64,
+    yielded: u64,
+    eps: Option<std::vec::IntoIter<Episode>>,
+    pages: Option<Pages>,
+    /// Whether the last page had every episode asked for, so more may follow
+    /// once more than `episodes_count` have arrived.
+    page_full: bool,
+    /// Whether yielded names are padded to `num_len`.
+    pad: bool,
+    slug: &'a mut Option<Box<str>>,
+    title: &'a mut Option<Box<str>>,
+    finish: bool,
+}
 
-    impl Iterator for Pages {
-        type Item = (u64, u64);
+impl<'a> InfoFetcher<'a> {
+    /// The anime slug, available once the first page has been fetched.
+    pub fn slug(&self) -> Option<&str> {
+        self.slug.as_deref()
+    }
 
-        fn next(&mut self) -> Option<Self::Item> {
-            if self.current < self.max {
-                let start = self.current;
-                self.current += 120;
-                let stop = (self.current - 1).min(self.max);
-                Some((start, stop))
-            } else {
-                None
-            }
+    /// The anime title, available once the first page has been fetched.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Yields the bare episode numbers as names instead of padding them with
+    /// leading zeros.
+    pub fn unpadded(mut self) -> Self {
+        self.pad = false;
+        self
+    }
+
+    /// Width names are padded to, known once the first page has been
+    /// fetched. It can grow when the site announced fewer episodes than it
+    /// has.
+    pub fn padding(&self) -> usize {
+        self.num_len
+    }
+
+    /// Episodes yielded so far and the total announced by the site, which
+    /// is 0 until the first page has been fetched.
+    pub fn progress(&self) -> (u64, u64) {
+        (self.yielded, self.episodes_count.max(self.yielded))
+    }
+
+    /// Resolves the video of each episode with `resolve` as it is yielded.
+    pub fn resolved<F>(self, resolve: F) -> ResolvedVideos<'a, F>
+    where
+        F: FnMut(&Episode) -> Result<Video>,
+    {
+        ResolvedVideos {
+            info: self,
+            resolve,
+            strict: false,
+            done: false,
         }
     }
+}
+
+/// Episodes along with their video, see [`resolved_videos`].
+///
+/// An episode whose video cannot be resolved is yielded as an error and the
+/// following ones are still resolved, unless [`ResolvedVideos::strict`] is
+/// set.
+pub struct ResolvedVideos<'a, F> {
+    info: InfoFetcher<'a>,
+    resolve: F,
+    strict: bool,
+    done: bool,
+}
+
+impl<'a, F> ResolvedVideos<'a, F> {
+    /// Ends the iteration at the first error.
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
 
-    struct InfoFetcher<'a> {
-        id: u64,
-        num_len: usize,
-        eps: Option<std::vec::IntoIter<Episode>>,
-        pages: Option<Pages>,
-        slug: &'a mut Option<Box<str>>,
-        title: &'a mut Option<Box<str>>,
-        finish: bool,
+    #[inline]
+    pub fn slug(&self) -> Option<&str> {
+        self.info.slug()
     }
 
-    impl<'a> Iterator for InfoFetcher<'a> {
-        type Item = Result<(Box<str>, Episode)>;
+    #[inline]
+    pub fn title(&self) -> Option<&str> {
+        self.info.title()
+    }
+}
+
+impl<'a, F> Iterator for ResolvedVideos<'a, F>
+where
+    F: FnMut(&Episode) -> Result<Video>,
+{
+    type Item = Result<(Episode, Video)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let res = self
+            .info
+            .next()?
+            .and_then(|(_, ep)| match (self.resolve)(&ep) {
+                Ok(video) => Ok((ep, video)),
+                Err(err) => Err(err.context(format!("Episode {}", ep.number))),
+            });
+        self.done = self.strict && res.is_err();
+        Some(res)
+    }
+}
+
+impl<'a> Iterator for InfoFetcher<'a> {
+    type Item = Result<(Box<str>, Episode)>;
 
-        fn next(&mut self) -> Option<Self::Item> {
-            loop {
-                if let Some(mut eps) = self.eps.take() {
-                    if let Some(ep) = eps.next() {
-                        self.eps = Some(eps);
-                        let mut name = ep.number.clone();
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(mut eps) = self.eps.take() {
+                if let Some(mut ep) = eps.next() {
+                    self.eps = Some(eps);
+                    self.yielded += 1;
+                    // the announced count was stale, widen the padding
+                    if self.yielded > self.episodes_count {
+                        self.num_len = self.num_len.max(num_len(self.yielded));
+                    }
+                    normalize_number(&mut ep.number);
+                    let mut name = ep.number.clone();
+                    if self.pad {
                         name.pad_left(self.num_len);
-                        return Some(Ok((name.into(), ep)));
                     }
+                    return Some(Ok((name.into(), ep)));
                 }
+            }
 
-                if let Some(mut pages) = self.pages.take() {
-                    if let Some((start, stop)) = pages.next() {
-                        self.pages = Some(pages);
-                        match fetch_info_page(self.id, start, stop, self.slug, self.title) {
-                            Ok(mut i) => {
-                                if let Some(slug) = i.slug.take() {
-                                    *self.slug = Some(slug);
-                                }
-                                if let Some(title) = i.title.take() {
-                                    *self.title = Some(title);
-                                }
-                                self.eps = Some(i.episodes.into_iter());
-                                continue;
+            if let Some(mut pages) = self.pages.take() {
+                if let Some((start, stop)) = pages.next() {
+                    self.pages = Some(pages);
+                    match fetch_info_page(
+                        &*self.client,
+                        self.id,
+                        start,
+                        stop,
+                        self.slug,
+                        self.title,
+                    ) {
+                        Ok(mut i) => {
+                            if let Some(slug) = i.slug.take() {
+                                *self.slug = Some(slug);
                             }
-                            Err(err) => {
-                                self.finish = true;
-                                return Some(Err(err));
+                            if let Some(title) = i.title.take() {
+                                *self.title = Some(title);
                             }
+                            self.page_full = i.episodes.len() as u64 > stop - start;
+                            self.eps = Some(i.episodes.into_iter());
+                            continue;
+                        }
+                        Err(err) => {
+                            self.finish = true;
+                            return Some(Err(err));
+                        }
+                    }
+                } else if self.page_full && self.yielded > self.episodes_count {
+                    // the count proved stale; with a right one the last page
+                    // is full too, which alone is not worth another request
+                    pages.extend();
+                    self.pages = Some(pages);
+                    self.page_full = false;
+                    continue;
+                } else {
+                    self.finish = true;
+                    if self.yielded == 0 && self.episodes_count > 0 {
+                        return Some(Err(Error::NoEpisodes {
+                            anime_id: self.id,
+                            episodes_count: self.episodes_count,
                         }
-                    } else {
-                        self.finish = true;
+                        .into()));
                     }
                 }
+            }
 
-                if self.finish {
-                    return None;
-                }
+            if self.finish {
+                return None;
+            }
 
-                match fetch_info_page(self.id, 1, 120, self.slug, self.title) {
-                    Ok(mut info) => {
-                        if let Some(slug) = info.slug.take() {
-                            *self.slug = Some(slug);
-                        }
-                        if let Some(title) = info.title.take() {
-                            *self.title = Some(title);
-                        }
-                        self.eps = Some(info.episodes.into_iter());
-                        self.num_len = num_len(info.episodes_count);
-                        let mut pages = Pages::new(info.episodes_count);
-                        _ = pages.next();
-                        self.pages = Some(pages);
+            let page = fetch_info_page(
+                &*self.client,
+                self.id,
+                1,
+                InfoApiPath::PAGE_SIZE,
+                self.slug,
+                self.title,
+            );
+            match page {
+                Ok(mut info) => {
+                    if let Some(slug) = info.slug.take() {
+                        *self.slug = Some(slug);
                     }
-                    Err(err) => {
-                        self.finish = true;
-                        return Some(Err(err));
+                    if let Some(title) = info.title.take() {
+                        *self.title = Some(title);
                     }
-                };
-            }
+                    self.page_full = info.episodes.len() as u64 >= InfoApiPath::PAGE_SIZE;
+                    self.eps = Some(info.episodes.into_iter());
+                    self.num_len = num_len(info.episodes_count);
+                    self.episodes_count = info.episodes_count;
+                    let mut pages = Pages::new(info.episodes_count);
+                    _ = pages.next();
+                    self.pages = Some(pages);
+                }
+                Err(err) => {
+                    self.finish = true;
+                    return Some(Err(err));
+                }
+            };
         }
     }
+}
 
+pub fn fetch_info<'a>(
+    id: u64,
+    slug: &'a mut Option<Box<str>>,
+    title: &'a mut Option<Box<str>>,
+) -> InfoFetcher<'a> {
+    fetch_info_via(Arc::new(http::Backend), id, slug, title)
+}
+
+pub(crate) fn fetch_info_via<'a>(
+    client: Arc<dyn HttpClient>,
+    id: u64,
+    slug: &'a mut Option<Box<str>>,
+    title: &'a mut Option<Box<str>>,
+) -> InfoFetcher<'a> {
     InfoFetcher {
+        client,
         id,
         num_len: 0,
+        episodes_count: 0,
+        yielded: 0,
         eps: None,
         pages: None,
+        page_full: false,
+        pad: true,
         finish: false,
         slug,
         title,
     }
 }
 
+/// Like [`fetch_info`], resolving the video of every episode with
+/// [`fetch_video_infos`].
+pub fn resolved_videos<'a>(
+    id: u64,
+    slug: &'a mut Option<Box<str>>,
+    title: &'a mut Option<Box<str>>,
+) -> ResolvedVideos<'a, fn(&Episode) -> Result<Video>> {
+    fetch_info(id, slug, title).resolved(|ep| fetch_video_infos(ep.id))
+}
+
+/// Returns the JSON the anime page passes to its player, which holds most of
+/// the metadata the site has about the anime. Its shape is defined by the
+/// site and can change at any time.
+pub fn fetch_player_info(anime_id: u64, slug: &str) -> Result<serde_json::Value> {
+    fetch_player_info_via(&http::Backend, anime_id, slug)
+}
+
+pub(crate) fn fetch_player_info_via(
+    client: &dyn HttpClient,
+    anime_id: u64,
+    slug: &str,
+) -> Result<serde_json::Value> {
+    let body = match site::fetch(client, &format!("/anime/{anime_id}-{slug}")) {
+        Ok(body) => body,
+        Err(err) => match err.downcast_ref() {
+            Some(Error::HttpStatus { status: 404, .. }) => bail!(Error::AnimeNotFound { anime_id }),
+            _ => return Err(err.context("Invalid informations")),
+        },
+    };
+
+    // the page exists, so a missing player means a gate or a markup change
+    let anime = dom::html_first(
+        body.as_bytes(),
+        dom::filter_tag_attr("video-player", "anime"),
+    )
+    .ok_or_else(|| match gate(&body) {
+        Some(gate) => gate.into(),
+        None => anyhow!("Cannot find player informations in the anime page"),
+    })?;
+    serde_json::from_str(&anime).context("Invalid player informations")
+}
+
+/// Fetches the titles, the external ids and the episode count of an anime
+/// with a single request to its page.
+pub fn fetch_metadata(anime_id: u64, slug: &str) -> Result<AnimeMetadata> {
+    fetch_metadata_via(&http::Backend, anime_id, slug)
+}
+
+pub(crate) fn fetch_metadata_via(
+    client: &dyn HttpClient,
+    anime_id: u64,
+    slug: &str,
+) -> Result<AnimeMetadata> {
+    let info = fetch_player_info_via(client, anime_id, slug)?;
+    let mut meta: AnimeMetadata =
+        serde_json::from_value(info).context("Invalid player informations")?;
+    meta.id = anime_id;
+    meta.slug.get_or_insert_with(|| slug.into());
+    Ok(meta)
+}
+
+/// Looks up the slug of an anime with a single one-episode `info_api`
+/// request.
+pub fn resolve_slug(anime_id: u64) -> Result<Box<str>> {
+    resolve_slug_via(&http::Backend, anime_id)
+}
+
+pub(crate) fn resolve_slug_via(client: &dyn HttpClient, anime_id: u64) -> Result<Box<str>> {
+    resolve_slug_padding(client, anime_id).map(|(slug, _)| slug)
+}
+
+/// The slug along with the width [`fetch_padding`] gives, from the same
+/// request.
+fn resolve_slug_padding(client: &dyn HttpClient, anime_id: u64) -> Result<(Box<str>, usize)> {
+    let mut slug = None;
+    let mut title = Some(Box::default());
+
+    let info = fetch_info_page(client, anime_id, 1, 1, &mut slug, &mut title)?;
+    let slug = info
+        .slug
+        .filter(|s| !s.is_empty())
+        .ok_or(Error::SlugNotFound { anime_id })?;
+    Ok((slug, num_len(info.episodes_count)))
+}
+
+/// Width [`InfoFetcher::padding`] starts with for `anime_id`, looked up with
+/// a single one-episode `info_api` request.
+pub fn fetch_padding(anime_id: u64) -> Result<usize> {
+    fetch_padding_via(&http::Backend, anime_id)
+}
+
+pub(crate) fn fetch_padding_via(client: &dyn HttpClient, anime_id: u64) -> Result<usize> {
+    let (mut slug, mut title) = (Some(Box::default()), Some(Box::default()));
+    let info = fetch_info_page(client, anime_id, 1, 1, &mut slug, &mut title)?;
+    Ok(num_len(info.episodes_count))
+}
+
+/// Fetches a single episode of an anime by its episode id from the page of
+/// the episode, which takes two requests however long the series is. When
+/// the page has no episode informations the episodes are listed until the
+/// one with `episode_id` turns up instead.
+pub fn fetch_episode(anime_id: u64, episode_id: u64) -> Result<Episode> {
+    fetch_episode_via(Arc::new(http::Backend), anime_id, episode_id)
+}
+
+pub(crate) fn fetch_episode_via(
+    client: Arc<dyn HttpClient>,
+    anime_id: u64,
+    episode_id: u64,
+) -> Result<Episode> {
+    fetch_padded_episode_via(client, anime_id, episode_id).map(|(episode, _)| episode)
+}
+
+/// [`fetch_episode`] along with the width [`fetch_padding`] gives, without
+/// another request.
+pub fn fetch_padded_episode(anime_id: u64, episode_id: u64) -> Result<(Episode, usize)> {
+    fetch_padded_episode_via(Arc::new(http::Backend), anime_id, episode_id)
+}
+
+pub(crate) fn fetch_padded_episode_via(
+    client: Arc<dyn HttpClient>,
+    anime_id: u64,
+    episode_id: u64,
+) -> Result<(Episode, usize)> {
+    let not_found = Error::EpisodeNotFound {
+        anime_id,
+        episode_id,
+    };
+
+    let (slug, padding) = resolve_slug_padding(&*client, anime_id)?;
+    let body = match site::fetch(&*client, &format!("/anime/{anime_id}-{slug}/{episode_id}")) {
+        Ok(body) => body,
+        Err(err) => match err.downcast_ref() {
+            Some(Error::HttpStatus { status: 404, .. }) => bail!(not_found),
+            _ => return Err(err.context("Invalid informations")),
+        },
+    };
+
+    let Some(episode) = dom::html_first(
+        body.as_bytes(),
+        dom::filter_tag_attr("video-player", "episode"),
+    ) else {
+        return Ok((find_episode(client, anime_id, episode_id)?, padding));
+    };
+    let mut episode: Episode =
+        serde_json::from_str(&episode).context("Invalid episode informations")?;
+    // the page of an id not in the anime plays its first episode
+    if episode.id != episode_id {
+        bail!(not_found);
+    }
+    normalize_number(&mut episode.number);
+    Ok((episode, padding))
+}
+
+fn find_episode(client: Arc<dyn HttpClient>, anime_id: u64, episode_id: u64) -> Result<Episode> {
+    let mut slug = Some(Box::default());
+    let mut title = Some(Box::default());
+
+    for ep in fetch_info_via(client, anime_id, &mut slug, &mut title) {
+        let (_, episode) = ep?;
+        if episode.id == episode_id {
+            return Ok(episode);
+        }
+    }
+
+    bail!(Error::EpisodeNotFound {
+        anime_id,
+        episode_id
+    })
+}
+
+/// Trims `number` and strips the leading zeros the site sometimes pads it
+/// with, so `" 03 "` and `"007"` become `"3"` and `"7"` while `"0"` and
+/// `"0.5"` are kept.
+fn normalize_number(number: &mut String) {
+    number.trim_in_place();
+    let zeros = number
+        .bytes()
+        .zip(number.bytes().skip(1))
+        .take_while(|&(c, next)| c == b'0' && next.is_ascii_digit())
+        .count();
+    number.drain(..zeros);
+}
+
 trait PadLeft {
     fn pad_left(&mut self, size: usize);
 }
 
 impl PadLeft for String {
     fn pad_left(&mut self, size: usize) {
-        if size <= self.len() {
+        // `size` counts characters, the buffer is measured in bytes
+        let len = self.chars().count();
+        if size <= len {
             return;
         }
 
-        let pad_len = size - self.len();
-        unsafe {
-            let buf = self.as_mut_vec();
-            buf.reserve_exact(pad_len);
-            std::ptr::copy(buf.as_ptr(), buf.as_mut_ptr().add(pad_len), buf.len());
-            std::ptr::write_bytes(buf.as_mut_ptr(), b'0', pad_len);
-            buf.set_len(size);
-            buf.shrink_to_fit();
+        let mut padded = String::with_capacity(size - len + self.len());
+        padded.extend(std::iter::repeat_n('0', size - len));
+        padded.push_str(self);
+        *self = padded;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use super::*;
+
+    /// Serves `pages` by path whatever the domain, 404 for anything else.
+    /// Probes find every url alive but the `dead` ones.
+    #[derive(Default)]
+    struct FakeHttp {
+        pages: HashMap<String, String>,
+        dead: Vec<String>,
+        requested: Mutex<Vec<String>>,
+        probed: Mutex<Vec<String>>,
+    }
+
+    impl FakeHttp {
+        fn page(mut self, path: impl Into<String>, body: impl Into<String>) -> Self {
+            self.pages.insert(path.into(), body.into());
+            self
+        }
+
+        fn dead(mut self, url: impl Into<String>) -> Self {
+            self.dead.push(url.into());
+            self
+        }
+
+        fn requests(&self) -> usize {
+            self.requested.lock().unwrap().len()
+        }
+    }
+
+    impl HttpClient for FakeHttp {
+        fn get(&self, url: &str) -> Result<String> {
+            let path = url
+                .splitn(4, '/')
+                .nth(3)
+                .map_or("/".into(), |p| format!("/{p}"));
+            self.requested.lock().unwrap().push(path.clone());
+            match self.pages.get(&path) {
+                Some(body) => Ok(body.clone()),
+                None => bail!(Error::HttpStatus {
+                    status: 404,
+                    url: url.into(),
+                }),
+            }
+        }
+
+        fn probe(&self, url: &str) -> Result<bool> {
+            self.probed.lock().unwrap().push(url.into());
+            Ok(!self.dead.iter().any(|dead| dead == url))
+        }
+    }
+
+    fn episodes(numbers: &[&str]) -> Vec<Episode> {
+        numbers
+            .iter()
+            .enumerate()
+            .map(|(id, number)| Episode {
+                id: id as u64,
+                number: number.to_string(),
+                kind: None,
+                airdate: None,
+                duplicate: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn duplicate_numbers() {
+        let mut eps = episodes(&["1", "2", "2", "3", "2", "3"]);
+        mark_duplicate_numbers(&mut eps);
+        let labels = eps.iter().map(Episode::label).collect::<Vec<_>>();
+        assert_eq!(labels, ["1", "2a", "2b", "3a", "2c", "3b"]);
+        assert_eq!(eps[2].number, "2");
+    }
+
+    #[test]
+    fn many_duplicate_numbers() {
+        let mut eps = episodes(&["1"; 1000]);
+        mark_duplicate_numbers(&mut eps);
+        let suffixes = eps
+            .iter()
+            .map(|ep| ep.duplicate.clone().unwrap())
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(suffixes.len(), eps.len());
+        assert_eq!(eps[25].duplicate.as_deref(), Some("z"));
+        assert_eq!(eps[26].duplicate.as_deref(), Some("aa"));
+        assert_eq!(eps[701].duplicate.as_deref(), Some("zz"));
+        assert_eq!(eps[702].duplicate.as_deref(), Some("aaa"));
+    }
+
+    #[test]
+    fn episode_by_id() {
+        let http = Arc::new(
+            FakeHttp::default()
+                .page(
+                    InfoApiPath::new(7, 1, 1).to_string(),
+                    r#"{"slug":"anime","episodes_count":500,"episodes":[{"id":10,"number":"1"}]}"#,
+                )
+                .page(
+                    "/anime/7-anime/12",
+                    r#"<video-player episode="{&quot;id&quot;:12,&quot;number&quot;:&quot;02&quot;}"></video-player>"#,
+                )
+                .page(
+                    "/anime/7-anime/99",
+                    r#"<video-player episode="{&quot;id&quot;:10,&quot;number&quot;:&quot;1&quot;}"></video-player>"#,
+                ),
+        );
+
+        let (episode, padding) = fetch_padded_episode_via(http.clone(), 7, 12).unwrap();
+        assert_eq!((episode.id, &*episode.number), (12, "2"));
+        assert_eq!(padding, 3);
+        assert_eq!(http.requests(), 2);
+
+        for id in [99, 100] {
+            let err = fetch_episode_via(http.clone(), 7, id).unwrap_err();
+            assert_eq!(
+                err.downcast_ref(),
+                Some(&Error::EpisodeNotFound {
+                    anime_id: 7,
+                    episode_id: id
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn padding() {
+        let http = FakeHttp::default().page(
+            InfoApiPath::new(7, 1, 1).to_string(),
+            r#"{"episodes_count":150,"episodes":[{"id":10,"number":"1"}]}"#,
+        );
+        assert_eq!(fetch_padding_via(&http, 7).unwrap(), 3);
+    }
+
+    fn info(http: FakeHttp, id: u64) -> Result<Vec<(Box<str>, Episode)>> {
+        let (mut slug, mut title) = (None, None);
+        fetch_info_via(Arc::new(http), id, &mut slug, &mut title).collect()
+    }
+
+    #[test]
+    fn no_episodes() {
+        let first = InfoApiPath::new(7, 1, InfoApiPath::PAGE_SIZE).to_string();
+        for body in [
+            r#"{"slug":"anime","episodes_count":3,"episodes":[]}"#,
+            r#"{"slug":"anime","episodes_count":3}"#,
+        ] {
+            let err = info(FakeHttp::default().page(&first, body), 7).unwrap_err();
+            assert_eq!(
+                err.downcast_ref(),
+                Some(&Error::NoEpisodes {
+                    anime_id: 7,
+                    episodes_count: 3
+                })
+            );
+            assert_eq!(
+                err.to_string(),
+                "No episodes found for anime 7 (expected 3)"
+            );
+        }
+
+        let http = FakeHttp::default().page(first, r#"{"slug":"anime","episodes_count":0}"#);
+        assert!(info(http, 7).unwrap().is_empty());
+    }
+
+    #[test]
+    fn info_errors() {
+        let err = info(FakeHttp::default(), 7).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(Error::HttpStatus { status: 404, .. })
+        ));
+    }
+
+    #[test]
+    fn url_shapes() {
+        let series = (7, Some("anime"), None);
+        let episode = (7, Some("anime"), Some(12));
+        for (url, expected) in [
+            ("7", (7, None, None)),
+            ("https://www.animeunity.so/anime/7-anime", series),
+            ("https://animeunity.to/anime/7-anime/", series),
+            ("https://www.animeunity.so/watch/7-anime", series),
+            ("https://www.animeunity.so/anime/7", (7, None, None)),
+            ("https://www.animeunity.so/anime/7-anime/12", episode),
+            ("https://www.animeunity.so/anime/7-anime/watch/12", episode),
+            ("https://www.animeunity.so/watch/7-anime/12", episode),
+            (
+                "https://www.animeunity.so/anime/7-anime?episode=12",
+                episode,
+            ),
+            (
+                "https://www.animeunity.so/watch/7-anime?episode_id=12",
+                episode,
+            ),
+            (
+                "https://www.animeunity.so/anime/7-anime/12?episode=13",
+                episode,
+            ),
+        ] {
+            let anime = parse_url(url).unwrap();
+            assert_eq!(
+                (anime.anime_id, anime.slug.as_deref(), anime.episode),
+                expected,
+                "{url}"
+            );
+        }
+
+        for url in [
+            "https://example.com/anime/7-anime",
+            "https://www.animeunity.so/",
+            "https://www.animeunity.so/play/7-anime",
+            "https://www.animeunity.so/anime/anime-7",
+            "https://www.animeunity.so/anime/7-anime/twelve",
+            "https://www.animeunity.so/anime/7-anime/12/13",
+            "https://www.animeunity.so/anime/7-anime?episode=twelve",
+            "not a url",
+        ] {
+            assert!(parse_url(url).is_err(), "{url}");
+        }
+    }
+
+    #[test]
+    fn title_without_title_eng() {
+        let http = FakeHttp::default().page(
+            "/anime/7-kimi-ni-todoke",
+            include_str!("../tests/fixtures/anime_without_title_eng.html"),
+        );
+        let title = |pref| {
+            let mut anime = parse_url("https://www.animeunity.so/anime/7-kimi-ni-todoke").unwrap();
+            anime
+                .fetch_requirements_via(&http, Requirements::TITLE, pref)
+                .unwrap();
+            anime.title.unwrap()
+        };
+
+        assert_eq!(&*title(TitlePreference::English), "Kimi ni Todoke");
+        assert_eq!(&*title(TitlePreference::Native), "Arrivare a te");
+
+        let meta = fetch_metadata_via(&http, 7, "kimi-ni-todoke").unwrap();
+        assert_eq!(meta.title_eng, None);
+        assert_eq!((meta.mal_id, meta.episodes_count), (Some(6045), Some(25)));
+    }
+
+    fn info_page(count: u64, numbers: std::ops::RangeInclusive<u64>) -> String {
+        let episodes = numbers
+            .map(|n| format!(r#"{{"id":{},"number":"{n}"}}"#, 1000 + n))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(r#"{{"slug":"anime","episodes_count":{count},"episodes":[{episodes}]}}"#)
+    }
+
+    #[test]
+    fn stale_episodes_count() {
+        let size = InfoApiPath::PAGE_SIZE;
+        let http = Arc::new(
+            FakeHttp::default()
+                .page(
+                    InfoApiPath::new(7, 1, size).to_string(),
+                    info_page(3, 1..=size),
+                )
+                .page(
+                    InfoApiPath::new(7, size + 1, 2 * size).to_string(),
+                    info_page(3, size + 1..=size + 5),
+                ),
+        );
+        let (mut slug, mut title) = (None, None);
+        let mut fetcher = fetch_info_via(http.clone(), 7, &mut slug, &mut title);
+        let names = fetcher
+            .by_ref()
+            .map(|ep| ep.map(|(name, _)| name))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(names.len() as u64, size + 5);
+        assert_eq!(fetcher.padding(), 3);
+        assert_eq!(fetcher.progress(), (size + 5, size + 5));
+        assert_eq!((&*names[0], &*names[9], &*names[99]), ("1", "10", "100"));
+        assert_eq!(&*names[size as usize + 4], "125");
+        assert_eq!(http.requests(), 2);
+
+        // a right count ends the listing without asking for more
+        let http = Arc::new(
+            FakeHttp::default()
+                .page(
+                    InfoApiPath::new(7, 1, size).to_string(),
+                    info_page(size + 2, 1..=size),
+                )
+                .page(
+                    InfoApiPath::new(7, size + 1, size + 2).to_string(),
+                    info_page(size + 2, size + 1..=size + 2),
+                ),
+        );
+        let (mut slug, mut title) = (None, None);
+        let listed = fetch_info_via(http.clone(), 7, &mut slug, &mut title)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(listed.len() as u64, size + 2);
+        assert_eq!(http.requests(), 2);
+
+        // a short first page ends the listing whatever the count says
+        let http = FakeHttp::default().page(
+            InfoApiPath::new(7, 1, size).to_string(),
+            info_page(2, 1..=3),
+        );
+        assert_eq!(info(http, 7).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn normalized_numbers() {
+        for (raw, normalized) in [
+            (" 03 ", "3"),
+            ("007", "7"),
+            ("12", "12"),
+            ("0", "0"),
+            ("00", "0"),
+            ("0.5", "0.5"),
+            ("07.5", "7.5"),
+            ("010a", "10a"),
+            ("\t5\n", "5"),
+            ("", ""),
+            ("OVA", "OVA"),
+        ] {
+            let mut number = raw.to_string();
+            normalize_number(&mut number);
+            assert_eq!(number, normalized, "{raw:?}");
+        }
+
+        // padding applies to the normalized number, not on top of the site's
+        let http = FakeHttp::default().page(
+            InfoApiPath::new(7, 1, InfoApiPath::PAGE_SIZE).to_string(),
+            r#"{"episodes_count":100,"episodes":[{"id":1,"number":" 03 "},{"id":2,"number":"007"},{"id":3,"number":"12"}]}"#,
+        );
+        let names = info(http, 7)
+            .unwrap()
+            .into_iter()
+            .map(|(name, ep)| (name, ep.number))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("003".into(), "3".to_string()),
+                ("007".into(), "7".to_string()),
+                ("012".into(), "12".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn embed_url() {
+        let http = FakeHttp::default()
+            .page("/embed-url/1", "https://vixcloud.co/embed/1?token=a\n")
+            .page(
+                "/embed-url/2",
+                include_str!("../tests/fixtures/embed_url_error.html"),
+            )
+            .page("/embed-url/3", "vixcloud.co/embed/3")
+            .page(
+                "/embed-url/4",
+                r#"<form class="age-verification"><input name="password"></form>"#,
+            );
+
+        assert_eq!(
+            fetch_embed_url(&http, 1).unwrap(),
+            "https://vixcloud.co/embed/1?token=a"
+        );
+        for id in [2, 3] {
+            assert_eq!(
+                fetch_embed_url(&http, id).unwrap_err().downcast_ref(),
+                Some(&Error::EmbedNotFound { episode_id: id })
+            );
+        }
+        assert_eq!(
+            fetch_embed_url(&http, 4).unwrap_err().downcast_ref(),
+            Some(&Error::AccessRestricted)
+        );
+        assert!(matches!(
+            fetch_embed_url(&http, 5).unwrap_err().downcast_ref(),
+            Some(Error::HttpStatus { status: 404, .. })
+        ));
+    }
+
+    #[test]
+    fn numeric_numbers() {
+        for (number, value) in [
+            ("7", Some(7.0)),
+            (" 3 ", Some(3.0)),
+            ("0", Some(0.0)),
+            ("007", Some(7.0)),
+            ("7.5", Some(7.5)),
+            ("7,5", Some(7.5)),
+            ("12a", Some(12.0)),
+            ("7.5b", Some(7.5)),
+            ("7.", Some(7.0)),
+            ("12-13", Some(12.0)),
+            (".5", None),
+            ("OVA", None),
+            ("", None),
+        ] {
+            let episode = &episodes(&[number])[0];
+            assert_eq!(episode.number_f64(), value, "{number:?}");
+        }
+    }
+
+    #[test]
+    fn unicode_slugs_and_titles() {
+        for url in [
+            "https://www.animeunity.so/anime/7-citt%C3%A0-d%C3%A9j%C3%A0-vu/12",
+            "https://www.animeunity.so/anime/7-città-déjà-vu/12",
+        ] {
+            let anime = parse_url(url).unwrap();
+            assert_eq!(anime.slug.as_deref(), Some("città-déjà-vu"), "{url}");
+            assert_eq!(anime.episode, Some(12));
         }
+        // not valid UTF-8 once decoded, kept as written
+        let anime = parse_url("https://www.animeunity.so/anime/7-a%FF").unwrap();
+        assert_eq!(anime.slug.as_deref(), Some("a%FF"));
+
+        let title = "Perché l'amore è così: una storia lunghissima ".repeat(40);
+        let player = serde_json::json!({ "title_eng": title }).to_string();
+        let path = format!("/archivio/?title={}", urlencoding::encode(&title));
+        assert!(path.is_ascii());
+        let http = FakeHttp::default()
+            .page(
+                "/anime/7-anime",
+                format!(
+                    r#"<video-player anime="{}"></video-player>"#,
+                    player.replace('"', "&quot;")
+                ),
+            )
+            .page(
+                path,
+                r#"<archivio records="[{&quot;id&quot;:7,&quot;mal_id&quot;:1,&quot;anilist_id&quot;:2}]"></archivio>"#,
+            );
+        let mut anime = parse_url("https://www.animeunity.so/anime/7-anime").unwrap();
+        anime
+            .fetch_requirements_via(
+                &http,
+                Requirements::MAL_ID | Requirements::ANILIST_ID,
+                TitlePreference::English,
+            )
+            .unwrap();
+        assert_eq!(anime.title.as_deref(), Some(&*title));
+        assert_eq!((anime.mal_id, anime.anilist_id), (Some(1), Some(2)));
+    }
+
+    #[test]
+    fn info_api_path() {
+        assert_eq!(
+            InfoApiPath::new(7, 1, 120).to_string(),
+            "/info_api/7/1?start_range=1&end_range=120"
+        );
+        assert_eq!(
+            InfoApiPath::new(7, 121, 240).to_string(),
+            "/info_api/7/1?start_range=121&end_range=240"
+        );
+        let path = InfoApiPath {
+            page: 3,
+            ..InfoApiPath::new(7, 1, 1)
+        };
+        assert_eq!(path.to_string(), "/info_api/7/3?start_range=1&end_range=1");
+
+        // the second page starts right after the first
+        let http = Arc::new(
+            FakeHttp::default()
+                .page(
+                    "/info_api/7/1?start_range=1&end_range=120",
+                    info_page(130, 1..=120),
+                )
+                .page(
+                    "/info_api/7/1?start_range=121&end_range=130",
+                    info_page(130, 121..=130),
+                ),
+        );
+        let (mut slug, mut title) = (None, None);
+        assert_eq!(
+            fetch_info_via(http.clone(), 7, &mut slug, &mut title).count(),
+            130
+        );
+        assert_eq!(http.requests(), 2);
+    }
+
+    #[test]
+    fn pad_left() {
+        for (number, size, padded) in [
+            ("7", 3, "007"),
+            ("12", 2, "12"),
+            ("123", 2, "123"),
+            ("", 2, "00"),
+            ("7.5", 4, "07.5"),
+            // padded by characters, not bytes
+            ("½", 3, "00½"),
+            ("7½", 3, "07½"),
+            ("第3話", 3, "第3話"),
+            ("第3話", 5, "00第3話"),
+            ("😀", 2, "0😀"),
+        ] {
+            let mut name = number.to_string();
+            name.pad_left(size);
+            assert_eq!(name, padded, "{number:?} to {size}");
+        }
+    }
+
+    #[test]
+    fn helper_scripts() {
+        let body = r#"<html><head>
+            <script>var u = "https://example.com/test.mp4";</script>
+            <script>window.video = {filename: "test.mp4"}; window.downloadUrl = u;</script>
+            </head></html>"#;
+
+        let code = assemble_script(body, &ScriptLimits::default()).unwrap();
+        assert!(code.contains("var u"));
+        let limits = ScriptLimits {
+            skip_irrelevant: true,
+            ..Default::default()
+        };
+        assert!(!assemble_script(body, &limits).unwrap().contains("var u"));
+
+        if js::engines().next().is_none() {
+            return;
+        }
+        let video = js::extract_video_infos(code).unwrap();
+        assert_eq!(&*video.url, "https://example.com/test.mp4");
+    }
+
+    #[test]
+    fn live_video_through_client() {
+        if js::engines().next().is_none() {
+            return;
+        }
+        let download = "https://au-d1-07.example.org/DDL/ANIME/SampleShow/SampleShow_Ep_07_SUB_ITA.mp4?token=0123456789abcdef&expires=1700000000";
+        let http = FakeHttp::default()
+            .page("/embed-url/5", "https://vix.example.org/embed/5")
+            .page("/embed/5", include_str!("../benches/fixtures/embed.html"))
+            .dead(download);
+
+        let video = fetch_live_video_infos_via(&http, 5, &ScriptLimits::default()).unwrap();
+        assert_eq!(video.server.as_deref(), Some("Server1"));
+        let probed = http.probed.lock().unwrap();
+        assert_eq!(probed.len(), 2);
+        assert_eq!(probed[0], download);
+        assert_eq!(probed[1], &*video.url);
+        assert_eq!(http.requests(), 2);
+    }
+
+    #[test]
+    fn info_through_client() {
+        let http = Arc::new(FakeHttp::default().page(
+            InfoApiPath::new(7, 1, InfoApiPath::PAGE_SIZE).to_string(),
+            r#"{"slug":"anime","name":"Anime","episodes_count":2,"episodes":[{"id":1,"number":"1"},{"id":2,"number":"2"}]}"#,
+        ));
+        let (mut slug, mut title) = (None, None);
+        let ids = fetch_info_via(http.clone(), 7, &mut slug, &mut title)
+            .map(|ep| ep.map(|(_, ep)| ep.id))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(ids, [1, 2]);
+        assert_eq!(
+            (slug.as_deref(), title.as_deref()),
+            (Some("anime"), Some("Anime"))
+        );
+        assert_eq!(http.requests(), 1);
     }
 }
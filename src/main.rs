@@ -1,166 +1,1267 @@
+mod cli;
 mod config;
+mod selection;
 pub use audown::*;
 
-use std::fmt;
+use std::{
+    io::Write,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
 
-use anyhow::Result;
-use dialoguer::{theme::ColorfulTheme, MultiSelect};
-use directories::ProjectDirs;
-use template::Variables;
+use anyhow::{anyhow, bail, Context, Result};
+use dialoguer::{
+    theme::{ColorfulTheme, SimpleTheme, Theme},
+    Input, MultiSelect,
+};
 
-#[derive(Debug, Clone)]
-pub struct EpisodeVariables<'a> {
-    anime: &'a AnimeContext,
-    video: &'a Video,
-    episode: &'a Episode,
+use cli::Format;
+
+const EXIT_NOTHING_SELECTED: i32 = 3;
+const EXIT_CANCELLED: i32 = 130;
+const CHECK_JOBS: usize = 8;
+const FILTER_THRESHOLD: usize = 100;
+const DOCTOR_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+fn usage() {
+    println!(
+        "USAGE: {} [--profile <NAME>] [--<executor>|--mpv|--no-default-executor] [--title <english|romaji|native>] [--stats] [--cookies] [--only-specials|--no-specials] [--server <NAME>] [--since <DATE>] [--until <DATE>] [--include-undated] [--episodes <RANGES>] [--ids <ID,...>] [--select-file <PATH>] [--yes] [--print-command] [--remember] [--forget] [--include-watched] [--check] [--list-only] [--no-filter] [--dump-js <PATH>] [--limit-rate <RATE>] {} <URL|ID>",
+        std::env::args().next().unwrap(),
+        cli::HTTP_USAGE
+    );
+    println!(
+        "       {} --format <tsv|html> [--resolve] [--no-header] <URL|ID>",
+        std::env::args().next().unwrap()
+    );
+    println!(
+        "       {} --check [--ids <ID,...>] <URL|ID|PLAYLIST> | --check --ids <ID,...>",
+        std::env::args().next().unwrap()
+    );
+    println!(
+        "       {} --print-config-path | --init-config | --version [--format <text|json>] | --list-executors [--verbose] | --doctor",
+        std::env::args().next().unwrap()
+    );
+    println!();
+    cli::print_http_help();
+    if let Some(cfg) = config::path() {
+        println!();
+        println!("config: {}", cfg.display());
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum EpisodeValue<'a> {
-    Str(&'a str),
-    U64(u64),
+enum Command {
+    /// Without a url only with `--check --ids`.
+    Run {
+        url: Option<String>,
+    },
+    PrintConfigPath,
+    InitConfig,
+    Version,
+    ListExecutors,
+    Doctor,
 }
 
-impl<'a> fmt::Display for EpisodeValue<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            EpisodeValue::Str(s) => fmt::Display::fmt(s, f),
-            EpisodeValue::U64(s) => fmt::Display::fmt(s, f),
+struct Args {
+    command: Command,
+    executor: Option<String>,
+    title: Option<TitlePreference>,
+    stats: bool,
+    cookies: bool,
+    specials: Option<bool>,
+    server: Option<String>,
+    check: bool,
+    no_default_executor: bool,
+    since: Option<Date>,
+    until: Option<Date>,
+    include_undated: bool,
+    format: Option<Format>,
+    dump_js: Option<String>,
+    filter: Option<bool>,
+    resolve: bool,
+    header: bool,
+    limit_rate: Option<u64>,
+    ids: Option<Vec<u64>>,
+    episodes: Option<EpisodeRanges>,
+    yes: bool,
+    mpv: bool,
+    remember: bool,
+    forget: bool,
+    include_watched: bool,
+    select_file: Option<String>,
+    print_command: bool,
+    profile: Option<String>,
+    verbose: bool,
+    list_only: bool,
+    http: cli::HttpArgs,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut url = None;
+    let mut command = None;
+    let mut executor = None;
+    let mut title = None;
+    let mut stats = false;
+    let mut cookies = false;
+    let mut specials = None;
+    let mut server = None;
+    let mut check = false;
+    let mut no_default_executor = false;
+    let mut since = None;
+    let mut until = None;
+    let mut include_undated = false;
+    let mut format = None;
+    let mut dump_js = None;
+    let mut filter = None;
+    let mut resolve = false;
+    let mut header = true;
+    let mut limit_rate = None;
+    let mut ids = None;
+    let mut episodes = None;
+    let mut yes = false;
+    let mut mpv = false;
+    let mut remember = false;
+    let mut forget = false;
+    let mut include_watched = false;
+    let mut select_file = None;
+    let mut print_command = false;
+    let mut profile = None;
+    let mut verbose = false;
+    let mut list_only = false;
+    let mut http_args = cli::HttpArgs::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if let Some(flag) = arg.strip_prefix("--") {
+            if http_args.parse(flag, &mut args)? {
+                continue;
+            }
+        }
+        match arg.strip_prefix("--") {
+            Some("title") => title = Some(cli::flag_value(&mut args, "title")?.parse()?),
+            Some("stats") => stats = true,
+            Some("cookies") => cookies = true,
+            Some("check") => check = true,
+            Some("no-filter") => filter = Some(false),
+            Some("resolve") => resolve = true,
+            Some("limit-rate") => {
+                limit_rate = Some(download::parse_rate(&cli::flag_value(
+                    &mut args,
+                    "limit-rate",
+                )?)?)
+            }
+            Some("no-header") => header = false,
+            Some("ids") => {
+                ids = Some(
+                    cli::flag_value(&mut args, "ids")?
+                        .split(',')
+                        .map(|id| {
+                            id.trim()
+                                .parse()
+                                .map_err(|_| anyhow!("Invalid episode id {id:?}"))
+                        })
+                        .collect::<Result<Vec<u64>>>()?,
+                )
+            }
+            Some("episodes") => episodes = Some(cli::flag_value(&mut args, "episodes")?.parse()?),
+            Some("yes") => yes = true,
+            Some("mpv") => mpv = true,
+            Some("remember") => remember = true,
+            Some("forget") => forget = true,
+            Some("include-watched") => include_watched = true,
+            Some("print-command") => print_command = true,
+            Some("profile") => profile = Some(cli::flag_value(&mut args, "profile")?),
+            Some("select-file") => select_file = Some(cli::flag_value(&mut args, "select-file")?),
+            Some("since") => since = Some(cli::flag_value(&mut args, "since")?.parse()?),
+            Some("until") => until = Some(cli::flag_value(&mut args, "until")?.parse()?),
+            Some("include-undated") => include_undated = true,
+            Some("no-default-executor") => no_default_executor = true,
+            Some("server") => server = Some(cli::flag_value(&mut args, "server")?),
+            Some("only-specials") if specials.is_none() => specials = Some(true),
+            Some("no-specials") if specials.is_none() => specials = Some(false),
+            Some("print-config-path") if command.is_none() => {
+                command = Some(Command::PrintConfigPath)
+            }
+            Some("init-config") if command.is_none() => command = Some(Command::InitConfig),
+            Some("version") if command.is_none() => command = Some(Command::Version),
+            Some("list-executors") if command.is_none() => command = Some(Command::ListExecutors),
+            Some("doctor") if command.is_none() => command = Some(Command::Doctor),
+            Some("verbose") => verbose = true,
+            Some("list-only") => list_only = true,
+            Some("dump-js") => dump_js = Some(cli::flag_value(&mut args, "dump-js")?),
+            Some("format") => format = Some(cli::flag_value(&mut args, "format")?.parse()?),
+            Some(e) if executor.is_none() => executor = Some(e.to_string()),
+            None if url.is_none() => url = Some(arg),
+            _ => {
+                usage();
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let command = match (command, url) {
+        (Some(command), None) => command,
+        (None, Some(url)) => Command::Run { url: Some(url) },
+        (None, None) if check && ids.is_some() => Command::Run { url: None },
+        _ => {
+            usage();
+            std::process::exit(1);
         }
+    };
+
+    Ok(Args {
+        command,
+        executor,
+        title,
+        stats,
+        cookies,
+        specials,
+        server,
+        check,
+        no_default_executor,
+        since,
+        until,
+        include_undated,
+        format,
+        dump_js,
+        filter,
+        resolve,
+        header,
+        limit_rate,
+        ids,
+        episodes,
+        yes,
+        mpv,
+        remember,
+        forget,
+        include_watched,
+        select_file,
+        print_command,
+        profile,
+        verbose,
+        list_only,
+        http: http_args,
+    })
+}
+
+/// Ends the program with a status, without an error message, once `_main`
+/// has returned and dropped everything.
+#[derive(Debug)]
+struct Exit(i32);
+
+impl std::fmt::Display for Exit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "exit status {}", self.0)
     }
 }
 
-impl<'a> EpisodeVariables<'a> {
-    #[inline]
-    pub fn new(anime: &'a AnimeContext, video: &'a Video, episode: &'a Episode) -> Self {
-        Self {
-            anime,
-            video,
-            episode,
+impl std::error::Error for Exit {}
+
+struct StatsSummary(Instant);
+
+impl Drop for StatsSummary {
+    fn drop(&mut self) {
+        let http::Stats { requests, bytes } = http::stats();
+        eprintln!(
+            "{requests} requests, {bytes} bytes downloaded in {:.2}s",
+            self.0.elapsed().as_secs_f64()
+        );
+    }
+}
+
+fn load_executor(
+    config: &mut config::Config,
+    name: Option<&str>,
+    explicit: bool,
+) -> Result<config::Executor> {
+    let (name, implicit) = match name {
+        Some(name) => (name.into(), false),
+        None if explicit => bail!("No executor selected (--no-default-executor)"),
+        None => (
+            config.defaults.executor.take().unwrap_or("default".into()),
+            true,
+        ),
+    };
+
+    match config.executors.remove(&*name) {
+        Some(executor) => Ok(executor),
+        None if matches!(&*name, "default" | "print") => Ok(config::Executor::Print(None)),
+        None if implicit => bail!("Invalid default executor {:?}", name),
+        None => {
+            output::error(format_args!("Invalid executor {:?}", name));
+            std::process::exit(1);
         }
     }
 }
 
-impl<'a> Variables for EpisodeVariables<'a> {
-    type Item<'b> = EpisodeValue<'b>
-    where
-        Self: 'b;
+/// Prints the names of the configured executors, marking the one used when
+/// none is given.
+fn list_executors(config: config::Config, verbose: bool) {
+    let default = config.defaults.executor.as_deref().unwrap_or("default");
+    let mut names: Vec<_> = config.executors.keys().map(String::as_str).collect();
+    if matches!(default, "default" | "print") && !names.contains(&default) {
+        // the built-in `print` executor
+        names.push(default);
+    }
+    names.sort_unstable();
 
-    #[allow(clippy::needless_lifetimes)]
-    fn get<'b, S: AsRef<str>>(&'b self, name: S) -> Option<Self::Item<'b>> {
-        let name = name.as_ref();
-        match name {
-            "slug" => self.anime.slug.as_deref().map(EpisodeValue::Str),
-            "title" => self.anime.title.as_deref().map(EpisodeValue::Str),
-            "mal_id" => self.anime.mal_id.map(EpisodeValue::U64),
-            "anilist_id" => self.anime.anilist_id.map(EpisodeValue::U64),
-            "episode" => Some(EpisodeValue::Str(&self.episode.number)),
-            "file" => Some(EpisodeValue::Str(&self.video.file)),
-            "url" => Some(EpisodeValue::Str(&self.video.url)),
-            _ => None,
+    for name in names {
+        let mark = if name == default { " (default)" } else { "" };
+        if verbose {
+            let desc = match config.executors.get(name) {
+                Some(ex) => ex.describe(),
+                None => config::Executor::Print(None).describe(),
+            };
+            println!("{name}{mark}: {desc}");
+        } else {
+            println!("{name}{mark}");
         }
     }
 }
 
-fn usage() {
-    println!(
-        "USAGE: {} [--<executor>] <URL|ID>",
-        std::env::args().next().unwrap()
-    );
-    let mut cfg = ProjectDirs::from("dev", "shurizzle", "AnimeUnity Downloader")
-        .unwrap()
-        .config_dir()
-        .to_path_buf();
-    cfg.push("config.yaml");
-    println!("config: {}", cfg.display());
-}
-
-fn load_executor(name: Option<&str>) -> Result<config::Executor> {
-    let Some(name) = name else {
-        return Ok(config::load()?
-            .remove("default")
-            .map(config::Executor::Command)
-            .unwrap_or(config::Executor::Print));
+/// Outcome of the `--doctor` checks, printed as they run.
+#[derive(Default)]
+struct Checklist {
+    failed: bool,
+}
+
+impl Checklist {
+    fn pass<T: std::fmt::Display>(&mut self, msg: T) {
+        println!("[ok]   {msg}");
+    }
+
+    fn warn<T: std::fmt::Display>(&mut self, msg: T, hint: &str) {
+        println!("[warn] {msg}");
+        println!("       {hint}");
+    }
+
+    /// A critical failure, making `--doctor` exit non-zero.
+    fn fail<T: std::fmt::Display>(&mut self, msg: T, hint: &str) {
+        println!("[fail] {msg}");
+        println!("       {hint}");
+        self.failed = true;
+    }
+}
+
+/// Checks the configuration, the backends compiled in and the reachability
+/// of the site, failing when something needed to download is broken.
+fn doctor(profile: Option<&str>) -> Result<()> {
+    let mut checks = Checklist::default();
+
+    let config = match config::path() {
+        None => {
+            checks.fail(
+                "config: cannot find the configuration directory",
+                "set HOME (or XDG_CONFIG_HOME) to a writable directory",
+            );
+            None
+        }
+        Some(path) => {
+            if !path.exists() {
+                checks.warn(
+                    format_args!("config: {} not found, using defaults", path.display()),
+                    "run --init-config to write an example to edit",
+                );
+            }
+            match config::load(profile) {
+                Ok(config) => {
+                    if path.exists() {
+                        checks.pass(format_args!("config: {} is valid", path.display()));
+                    }
+                    Some(config)
+                }
+                Err(err) => {
+                    checks.fail(
+                        format_args!("config: {err:#}"),
+                        "fix the file or the chosen profile, or move the file away and run --init-config",
+                    );
+                    None
+                }
+            }
+        }
     };
+    let mut config = config.unwrap_or_default();
+
+    checks.pass(format_args!("http backend: {}", http::BACKEND));
+    let engines = js::self_test();
+    let working = engines.iter().filter(|(_, res)| res.is_ok()).count();
+    if engines.is_empty() {
+        checks.warn(
+            "js engine: none compiled in, episodes can be listed but not downloaded",
+            "rebuild with the boa, v8, quickjs or quickjs-ng feature",
+        );
+    }
+    for (name, res) in engines {
+        match res {
+            Ok(()) => checks.pass(format_args!("js engine {name}: extraction works")),
+            Err(err) if working > 0 => checks.warn(
+                format_args!("js engine {name}: {err:#}"),
+                "the other engines are tried when it fails, AUDOWN_JS_ENGINE picks the first",
+            ),
+            Err(err) => checks.fail(
+                format_args!("js engine {name}: {err:#}"),
+                "rebuild with another engine and report the failure",
+            ),
+        }
+    }
+
+    let timeout = config
+        .defaults
+        .timeout
+        .map(http::secs_duration)
+        .transpose()
+        .unwrap_or_default()
+        .filter(|timeout| !timeout.is_zero())
+        .unwrap_or(DOCTOR_TIMEOUT);
+    let options = http::HttpOptions {
+        ca_bundle: config.defaults.ca_bundle.take(),
+        ip_version: config.defaults.ip_version.unwrap_or_default(),
+        accept_language: config.defaults.accept_language.take(),
+        proxy: config.defaults.proxy.take(),
+        user_agent: config.defaults.user_agent.take(),
+        headers: config
+            .defaults
+            .headers
+            .take()
+            .unwrap_or_default()
+            .into_iter()
+            .collect(),
+        timeout: Some(timeout),
+        connect_timeout: config
+            .defaults
+            .connect_timeout
+            .and_then(|secs| http::secs_duration(secs).ok()),
+        ..Default::default()
+    };
+    if let Err(err) = http::configure(options) {
+        checks.fail(
+            format_args!("http: {err:#}"),
+            "fix or remove ca_bundle and proxy in the configuration",
+        );
+    }
+
+    if let Some(domain) = config.defaults.domain.take() {
+        site::set_domain(domain);
+    }
+    let domains = site::domains();
+    let mut reachable = 0;
+    for (i, domain) in domains.iter().enumerate() {
+        let res = http::get(&format!("https://{domain}/"));
+        let err = match res {
+            Ok(_) => {
+                reachable += 1;
+                checks.pass(format_args!("{domain} is reachable"));
+                continue;
+            }
+            Err(err) => err,
+        };
+        let hint = match err.downcast_ref::<Error>() {
+            Some(Error::HttpStatus { .. }) => "the site answered, it may be down or have moved",
+            Some(Error::CloudflareChallenge | Error::RegionBlocked) => {
+                "domain blocked, try a proxy (proxy in the configuration or HTTPS_PROXY)"
+            }
+            _ => "domain unreachable, check the connection or try a proxy (proxy in the configuration or HTTPS_PROXY) or ip_version: ipv4",
+        };
+        // only critical when no domain is left to fall back to
+        if reachable == 0 && i + 1 == domains.len() {
+            checks.fail(format_args!("{domain}: {err:#}"), hint);
+        } else {
+            checks.warn(format_args!("{domain}: {err:#}"), hint);
+        }
+    }
+
+    if checks.failed {
+        bail!("Some critical checks failed");
+    }
+    Ok(())
+}
 
-    if let Some(executor) = config::load()?.remove(name).map(config::Executor::Command) {
-        Ok(executor)
+/// What `--check` probes.
+enum CheckTarget {
+    /// An episode, resolved before probing its video url.
+    Episode { id: u64, label: String },
+    /// A video url, probed as it is.
+    Url(String),
+}
+
+/// Reads the entries of a saved playlist, either an `aumpv` one or a list of
+/// urls or episode ids, one per line, as in an M3U file.
+fn read_playlist(path: &str) -> Result<Vec<CheckTarget>> {
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Item {
+        Url(String),
+        Named { url: String },
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Playlist {
+        items: Vec<Item>,
+    }
+
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("Cannot read playlist {path:?}"))?;
+    let entries: Vec<String> = if content.trim_start().starts_with('{') {
+        serde_json::from_str::<Playlist>(&content)
+            .with_context(|| format!("Invalid playlist {path:?}"))?
+            .items
+            .into_iter()
+            .map(|item| match item {
+                Item::Url(url) | Item::Named { url } => url,
+            })
+            .collect()
     } else {
-        println!("Invalid executor {:?}", name);
-        std::process::exit(1);
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Into::into)
+            .collect()
+    };
+
+    entries
+        .into_iter()
+        .map(|entry| {
+            if let Ok(id) = entry.parse() {
+                return Ok(CheckTarget::Episode {
+                    id,
+                    label: format!("Episode id {id}"),
+                });
+            }
+            // episode pages are resolved, anything else is a video url
+            Ok(match audown::parse_url(&entry) {
+                Ok(AnimeContext {
+                    episode: Some(id), ..
+                }) => CheckTarget::Episode { id, label: entry },
+                Ok(_) => bail!("{entry} is not an episode url"),
+                Err(_) => CheckTarget::Url(entry),
+            })
+        })
+        .collect()
+}
+
+/// Resolves every episode and checks that its video url is still alive.
+fn check_episodes(client: &AnimeClient, episodes: &[Episode]) -> Result<()> {
+    let targets: Vec<_> = episodes
+        .iter()
+        .map(|episode| CheckTarget::Episode {
+            id: episode.id,
+            label: format!("Episode {}", episode.number),
+        })
+        .collect();
+    check_targets(client, &targets)
+}
+
+/// Checks that the video url of every target is still alive, `CHECK_JOBS`
+/// at a time.
+fn check_targets(client: &AnimeClient, targets: &[CheckTarget]) -> Result<()> {
+    let next = AtomicUsize::new(0);
+    let alive = AtomicUsize::new(0);
+    let http = client.http_client();
+
+    std::thread::scope(|s| {
+        for _ in 0..CHECK_JOBS.min(targets.len()) {
+            s.spawn(|| {
+                while let Some(target) = targets.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let (label, res) = match target {
+                        CheckTarget::Episode { id, label } => (
+                            label.as_str(),
+                            client
+                                .fetch_video_infos(*id)
+                                .and_then(|video| Ok((http.probe(&video.url)?, video.url))),
+                        ),
+                        CheckTarget::Url(url) => (
+                            url.as_str(),
+                            http.probe(url).map(|alive| (alive, url.as_str().into())),
+                        ),
+                    };
+                    match res {
+                        Ok((true, _)) => {
+                            alive.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Ok((false, url)) if matches!(target, CheckTarget::Url(_)) => {
+                            output::error(format_args!("{url} is dead"))
+                        }
+                        Ok((false, url)) => output::error(format_args!("{label}: {url} is dead")),
+                        Err(err) => output::error(format_args!("{label}: {err:#}")),
+                    }
+                }
+            });
+        }
+    });
+
+    let alive = alive.into_inner();
+    let dead = targets.len() - alive;
+    println!("{alive} alive, {dead} dead");
+    if dead > 0 {
+        bail!("{dead} episode(s) are not available");
     }
+    Ok(())
 }
 
-fn _main() -> Result<()> {
-    let (url, ex) = match std::env::args().len() {
-        2 => (std::env::args().nth(1).unwrap(), load_executor(None)?),
-        3 => {
-            let (mut e, mut url) = {
-                let mut it = std::env::args().skip(1);
-                (it.next().unwrap(), it.next().unwrap())
-            };
+/// Whether every character of `pattern` appears in `text` in order,
+/// ignoring case.
+fn fuzzy_match(pattern: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    pattern
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| !c.is_whitespace())
+        .all(|p| text.any(|t| t == p))
+}
+
+/// Asks for a filter until it matches some episode, returning the indices
+/// of the matching ones.
+fn filter_episodes(theme: &dyn Theme, reprs: &[Box<str>]) -> Result<Vec<usize>> {
+    loop {
+        let pattern: String = Input::with_theme(theme)
+            .with_prompt(format!("Filter {} episodes (empty for all)", reprs.len()))
+            .allow_empty(true)
+            .interact_text()?;
+        let shown: Vec<usize> = (0..reprs.len())
+            .filter(|&i| fuzzy_match(&pattern, &reprs[i]))
+            .collect();
+        if !shown.is_empty() {
+            return Ok(shown);
+        }
+        output::warning(format_args!("No episodes match {pattern:?}"));
+    }
+}
+
+fn tsv_field(value: &str) -> std::borrow::Cow<'_, str> {
+    if value.contains(['\t', '\n', '\r']) {
+        value.replace(['\t', '\n', '\r'], " ").into()
+    } else {
+        value.into()
+    }
+}
+
+/// Prints `number`, `id`, `title` and, when `resolve` is set, `url` for
+/// every episode.
+fn list_tsv(
+    client: &AnimeClient,
+    anime: &AnimeContext,
+    episodes: &[Episode],
+    resolve: bool,
+    header: bool,
+) -> Result<()> {
+    let mut out = std::io::stdout().lock();
+    if header {
+        write!(out, "number\tid\ttitle")?;
+        if resolve {
+            write!(out, "\turl")?;
+        }
+        writeln!(out)?;
+    }
+
+    let title = tsv_field(anime.title.as_deref().unwrap_or_default());
+    for episode in episodes {
+        write!(
+            out,
+            "{}\t{}\t{title}",
+            tsv_field(&episode.number),
+            episode.id
+        )?;
+        if resolve {
+            match client.fetch_video_infos(episode.id) {
+                Ok(video) => write!(out, "\t{}", tsv_field(&video.url))?,
+                Err(err) => {
+                    output::error(format_args!("Episode {}: {err:#}", episode.number));
+                    write!(out, "\t")?;
+                }
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
 
-            if url.starts_with("--") {
-                std::mem::swap(&mut e, &mut url);
+/// Prints the episodes without resolving their videos: their padded
+/// numbers as text, or the same lists as `--format` otherwise.
+fn list_episodes(
+    client: &AnimeClient,
+    anime: &AnimeContext,
+    episodes: &[Episode],
+    reprs: &[Box<str>],
+    format: Format,
+    header: bool,
+) -> Result<()> {
+    match format {
+        Format::Text => {
+            let mut out = std::io::stdout().lock();
+            for (repr, episode) in reprs.iter().zip(episodes) {
+                match episode.airdate {
+                    Some(date) => writeln!(out, "{repr}\t{}\t{date}", episode.id)?,
+                    None => writeln!(out, "{repr}\t{}", episode.id)?,
+                }
             }
+            Ok(())
+        }
+        Format::Json => {
+            serde_json::to_writer(std::io::stdout().lock(), episodes)?;
+            println!();
+            Ok(())
+        }
+        Format::Tsv => list_tsv(client, anime, episodes, false, header),
+        Format::Html => list_html(client, anime, episodes, false),
+    }
+}
 
-            if let Some(e) = e.strip_prefix("--") {
-                let executor = load_executor(if e == "default" { None } else { Some(e) })?;
+fn html_escape(value: &str) -> std::borrow::Cow<'_, str> {
+    if !value.contains(['&', '<', '>', '"', '\'']) {
+        return value.into();
+    }
 
-                (url, executor)
-            } else {
-                usage();
-                std::process::exit(1);
+    let mut escaped = String::with_capacity(value.len() + 16);
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.into()
+}
+
+// keeps `javascript:` and the like out of the page
+fn is_http(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+/// Writes a self-contained html page with the anime title, its cover and a
+/// table of the episodes linking to their page or, when `resolve` is set, to
+/// their video.
+fn list_html(
+    client: &AnimeClient,
+    anime: &AnimeContext,
+    episodes: &[Episode],
+    resolve: bool,
+) -> Result<()> {
+    let title = anime.title.as_deref().unwrap_or_default();
+    let cover = anime.slug.as_deref().and_then(|slug| {
+        match client.fetch_player_info(anime.anime_id, slug) {
+            Ok(info) => info
+                .get("imageurl")?
+                .as_str()
+                .filter(|url| is_http(url))
+                .map(str::to_string),
+            Err(err) => {
+                output::debug(format_args!("Cannot fetch the cover: {err:#}"));
+                None
             }
         }
-        _ => {
-            usage();
-            std::process::exit(1);
+    });
+
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>{}</title>", html_escape(title))?;
+    writeln!(
+        out,
+        "<style>body{{font-family:sans-serif}}td,th{{padding:2px 8px;text-align:left}}</style>"
+    )?;
+    writeln!(out, "</head>\n<body>\n<h1>{}</h1>", html_escape(title))?;
+    if let Some(cover) = cover {
+        writeln!(out, "<img src=\"{}\" alt=\"\">", html_escape(&cover))?;
+    }
+    writeln!(
+        out,
+        "<table>\n<tr><th>Episode</th><th>Aired</th><th>Link</th></tr>"
+    )?;
+
+    for episode in episodes {
+        let link = if resolve {
+            match client.fetch_video_infos(episode.id) {
+                Ok(video) => Some(video.url.to_string()).filter(|url| is_http(url)),
+                Err(err) => {
+                    output::error(format_args!("Episode {}: {err:#}", episode.number));
+                    None
+                }
+            }
+        } else {
+            anime.slug.as_deref().map(|slug| {
+                site::url(&format!(
+                    "/anime/{}-{}/{}",
+                    anime.anime_id,
+                    urlencoding::Encoded(slug),
+                    episode.id
+                ))
+            })
+        };
+
+        write!(
+            out,
+            "<tr><td>{}</td><td>{}</td><td>",
+            html_escape(&episode.label()),
+            episode.airdate.map(|d| d.to_string()).unwrap_or_default()
+        )?;
+        if let Some(link) = link {
+            let link = html_escape(&link);
+            write!(out, "<a href=\"{link}\">{link}</a>")?;
         }
+        writeln!(out, "</td></tr>")?;
+    }
+
+    writeln!(out, "</table>\n</body>\n</html>")?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Numbers of the episodes the `watched` hook reports as already seen.
+fn watched_episodes(
+    client: &AnimeClient,
+    anime: &mut AnimeContext,
+    hook: &config::CommandExecutor,
+) -> Result<Vec<String>> {
+    let reqs = Requirements::from_variables(hook.variables());
+    if !reqs.is_empty() {
+        client.fetch_requirements(anime, reqs)?;
+    }
+
+    let mut vars = std::collections::HashMap::<&str, String>::new();
+    vars.insert("anime_id", anime.anime_id.to_string());
+    let known = [
+        ("slug", anime.slug.as_deref().map(str::to_string)),
+        ("title", anime.title.as_deref().map(str::to_string)),
+        ("mal_id", anime.mal_id.map(|id| id.to_string())),
+        ("anilist_id", anime.anilist_id.map(|id| id.to_string())),
+    ];
+    vars.extend(known.into_iter().filter_map(|(k, v)| Some((k, v?))));
+
+    Ok(hook
+        .output(&vars)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Whether `s` names `episode` by its label or its number, compared
+/// numerically so `7` matches `07`.
+fn matches_number(s: &str, episode: &Episode) -> bool {
+    s == episode.label()
+        || s == episode.number.trim()
+        || episode.number_f64().is_some_and(|n| s.parse() == Ok(n))
+}
+
+fn find_program(name: &str) -> Option<std::path::PathBuf> {
+    let file = if cfg!(windows) {
+        format!("{name}.exe")
+    } else {
+        name.to_string()
     };
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+}
+
+fn print_version(format: Format) -> Result<()> {
+    let version = env!("CARGO_PKG_VERSION");
+    let engines = js::engines().collect::<Vec<_>>();
+    let js = if engines.is_empty() {
+        js::ENGINE.to_string()
+    } else {
+        engines.join(", ")
+    };
+    match format {
+        Format::Text | Format::Tsv | Format::Html => println!(
+            "{} {version} (http: {}, js: {})",
+            env!("CARGO_PKG_NAME"),
+            http::BACKEND,
+            js
+        ),
+        Format::Json => {
+            serde_json::to_writer(
+                std::io::stdout().lock(),
+                &serde_json::json!({
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": version,
+                    "http": http::BACKEND,
+                    "js": js::ENGINE,
+                    "js_engines": engines,
+                }),
+            )?;
+            println!();
+        }
+    }
+    Ok(())
+}
+
+fn _main() -> Result<()> {
+    let Args {
+        command,
+        executor,
+        title,
+        stats,
+        cookies,
+        specials,
+        server,
+        check,
+        no_default_executor,
+        since,
+        until,
+        include_undated,
+        format,
+        dump_js,
+        filter,
+        resolve,
+        header,
+        limit_rate,
+        ids,
+        episodes,
+        yes,
+        mpv,
+        remember,
+        forget,
+        include_watched,
+        select_file,
+        print_command,
+        profile,
+        verbose,
+        list_only,
+        http: http_args,
+    } = parse_args()?;
 
-    let mut anime = parse_url(&url)?;
+    // read before anything is fetched, so a missing file fails fast
+    let answers = select_file
+        .map(|path| {
+            std::fs::read_to_string(&path)
+                .with_context(|| format!("Cannot read selection file {path:?}"))
+        })
+        .transpose()?
+        .map(|content| {
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        });
+
+    let url = match command {
+        Command::Run { url } => url,
+        Command::PrintConfigPath => {
+            let cfg =
+                config::path().ok_or_else(|| anyhow!("Cannot find configuration directory"))?;
+            println!("{}", cfg.display());
+            return Ok(());
+        }
+        Command::Version => {
+            let format = format.or_else(|| config::load(profile.as_deref()).ok()?.defaults.format);
+            print_version(format.unwrap_or_default())?;
+            return Ok(());
+        }
+        Command::ListExecutors => {
+            list_executors(config::load(profile.as_deref())?, verbose);
+            return Ok(());
+        }
+        Command::Doctor => return doctor(profile.as_deref()),
+        Command::InitConfig => {
+            let cfg = config::init()?;
+            output::success(format_args!("Configuration written to {}", cfg.display()));
+            return Ok(());
+        }
+    };
+
+    let mut config = config::load(profile.as_deref())?;
+    let stats = stats || config.defaults.stats.unwrap_or(false);
+    let _stats = stats.then(|| StatsSummary(Instant::now()));
+    if let Some(path) = dump_js {
+        // kept where it was given when the configured directory is entered
+        js::set_dump_path(Some(std::path::absolute(path)?));
+    }
+    let directory = config.defaults.directory.take();
+    let no_default_executor =
+        no_default_executor || config.defaults.require_executor.unwrap_or(false);
+    // `--mpv` plays the selected episodes instead of running an executor
+    // `--list-only` never resolves a video, so it works without a js engine
+    let (ex, mpv) = if list_only {
+        if mpv || executor.is_some() || print_command || resolve || check {
+            bail!("--list-only cannot be used with an executor, --mpv, --print-command, --resolve or --check");
+        }
+        (None, None)
+    } else if mpv {
+        if executor.is_some() {
+            bail!("--mpv cannot be used with an executor");
+        }
+        if print_command {
+            bail!("--print-command cannot be used with --mpv");
+        }
+        let path = find_program("mpv").ok_or_else(|| {
+            anyhow!("Cannot find mpv in PATH, install it or use an executor instead of --mpv")
+        })?;
+        (None, Some(path))
+    } else {
+        let ex = load_executor(&mut config, executor.as_deref(), no_default_executor)?;
+        if print_command && !matches!(ex, config::Executor::Command(_)) {
+            bail!("--print-command needs a command executor");
+        }
+        (Some(ex), None)
+    };
+    let specials = specials.or(match config.defaults.specials {
+        Some(config::Specials::Only) => Some(true),
+        Some(config::Specials::Exclude) => Some(false),
+        Some(config::Specials::All) | None => None,
+    });
+    let limit_rate = match limit_rate {
+        Some(rate) => Some(rate),
+        None => config
+            .defaults
+            .limit_rate
+            .as_deref()
+            .map(download::parse_rate)
+            .transpose()
+            .context("Error in configuration file")?,
+    };
+    download::set_rate_limit(limit_rate);
+    let include_undated = include_undated || config.defaults.include_undated.unwrap_or(false);
+    let filter = filter.or(config.defaults.filter).unwrap_or(true);
+    let format = format.or(config.defaults.format).unwrap_or_default();
+    let mut options = ClientOptions {
+        domain: config.defaults.domain.take(),
+        title: title.or(config.defaults.title).unwrap_or_default(),
+        probe: config.defaults.probe.unwrap_or(false),
+        server: server.map(Into::into).or(config.defaults.server.take()),
+        video_ttl: config
+            .defaults
+            .video_ttl
+            .map(http::secs_duration)
+            .transpose()
+            .context("Error in configuration file")?,
+        ..Default::default()
+    };
+    options.http = http_args.into_options(&mut config.defaults, cookies)?;
+    if let Some(max_size) = config.defaults.max_script_size {
+        options.limits.max_size = max_size;
+    }
+    if let Some(max_scripts) = config.defaults.max_scripts {
+        options.limits.max_scripts = max_scripts;
+    }
+    if let Some(skip) = config.defaults.skip_irrelevant_scripts {
+        options.limits.skip_irrelevant = skip;
+    }
+    let client = AnimeClient::new(options)?;
+
+    // episode ids or a saved playlist are checked without listing the anime
+    let url = match url {
+        Some(url) if !(check && Path::new(&url).is_file()) => url,
+        Some(path) => return check_targets(&client, &read_playlist(&path)?),
+        None => {
+            let targets: Vec<_> = ids
+                .into_iter()
+                .flatten()
+                .map(|id| CheckTarget::Episode {
+                    id,
+                    label: format!("Episode id {id}"),
+                })
+                .collect();
+            return check_targets(&client, &targets);
+        }
+    };
+
+    let mut anime = client.parse_url(&url)?;
+
+    let remember = remember || config.defaults.remember_selection.unwrap_or(false);
+    if forget {
+        selection::forget(anime.anime_id)?;
+    }
+    // an explicit selection wins over the saved one
+    let explicit = ids.is_some() || episodes.is_some() || answers.is_some();
+    let saved = if remember && !forget && !explicit && anime.episode.is_none() {
+        selection::load(anime.anime_id).unwrap_or_else(|err| {
+            output::warning(format_args!("{err:#}"));
+            None
+        })
+    } else {
+        None
+    };
 
     let mut defaults = Vec::new();
     let mut reprs = Vec::new();
     let mut data = Vec::new();
 
-    for ep in fetch_info(anime.anime_id, &mut anime.slug, &mut anime.title) {
+    let selected = anime.episode;
+    let mut out_of_range = 0usize;
+    let mut info = client.fetch_info(&mut anime);
+    let mut progress = output::Progress::new();
+    while let Some(ep) = info.next() {
+        let (loaded, total) = info.progress();
+        if total > 0 {
+            progress.update(format_args!("Loading episodes {loaded}/{total}"));
+        }
         let (no, episode) = ep?;
+        if specials.is_some_and(|only| only != (episode.kind() == EpisodeKind::Special)) {
+            continue;
+        }
+        if since.is_some() || until.is_some() {
+            let in_range = match episode.airdate {
+                Some(date) => since.is_none_or(|s| date >= s) && until.is_none_or(|u| date <= u),
+                None => include_undated,
+            };
+            if !in_range {
+                out_of_range += 1;
+                continue;
+            }
+        }
 
-        defaults.push(anime.episode.map_or(true, |epno| episode.id == epno));
+        defaults.push(if explicit {
+            ids.as_ref().is_some_and(|ids| ids.contains(&episode.id))
+                || episodes.as_ref().is_some_and(|r| r.contains(&episode))
+                || answers
+                    .iter()
+                    .flatten()
+                    .any(|a| matches_number(a, &episode) || a.parse() == Ok(episode.id))
+        } else if let Some(saved) = &saved {
+            saved.contains(&episode.id)
+        } else {
+            selected.is_none_or(|epno| episode.id == epno)
+        });
         reprs.push(no);
         data.push(episode);
     }
+    let padding = info.padding();
+    let seasons = config.seasons.remove(&anime.anime_id);
+    drop(progress);
+
+    if data.is_empty() {
+        if out_of_range > 0 {
+            bail!("No episodes in the selected date range");
+        }
+        bail!("No episodes found");
+    }
+
+    mark_duplicate_numbers(&mut data);
+    for (repr, episode) in reprs.iter_mut().zip(&data) {
+        if let Some(c) = &episode.duplicate {
+            *repr = format!("{repr}{c}").into();
+        }
+    }
 
-    let selections = MultiSelect::with_theme(&ColorfulTheme::default())
-        .items(reprs.as_slice())
-        .defaults(defaults.as_slice())
-        .max_length(120)
-        .interact_opt()?;
-    let mut selections = if let Some(s) = selections {
-        s
+    for id in ids.iter().flatten() {
+        if !data.iter().any(|ep| ep.id == *id) {
+            output::warning(format_args!("Episode id {id} not found"));
+        }
+    }
+    for answer in answers.iter().flatten() {
+        if !data
+            .iter()
+            .any(|ep| matches_number(answer, ep) || answer.parse() == Ok(ep.id))
+        {
+            output::warning(format_args!("Episode {answer:?} not found"));
+        }
+    }
+
+    if check {
+        if explicit || selected.is_some() {
+            data = data
+                .into_iter()
+                .zip(&defaults)
+                .filter_map(|(ep, &keep)| keep.then_some(ep))
+                .collect();
+        }
+        return check_episodes(&client, &data);
+    }
+
+    if list_only {
+        if explicit || selected.is_some() {
+            data = data
+                .into_iter()
+                .zip(&defaults)
+                .filter_map(|(ep, &keep)| keep.then_some(ep))
+                .collect();
+            reprs = reprs
+                .into_iter()
+                .zip(&defaults)
+                .filter_map(|(repr, &keep)| keep.then_some(repr))
+                .collect();
+        }
+        return list_episodes(&client, &anime, &data, &reprs, format, header);
+    }
+
+    match format {
+        Format::Tsv => return list_tsv(&client, &anime, &data, resolve, header),
+        Format::Html => return list_html(&client, &anime, &data, resolve),
+        Format::Text | Format::Json => (),
+    }
+
+    if let Some(hook) = config
+        .defaults
+        .watched
+        .as_ref()
+        .filter(|_| !include_watched)
+    {
+        if !explicit && selected.is_none() {
+            match watched_episodes(&client, &mut anime, hook) {
+                Ok(watched) => {
+                    for (default, episode) in defaults.iter_mut().zip(&data) {
+                        if watched.iter().any(|w| matches_number(w, episode)) {
+                            *default = false;
+                        }
+                    }
+                }
+                Err(err) => output::warning(format_args!("Watched episodes hook: {err:#}")),
+            }
+        }
+    }
+
+    // a selection file answers the prompt
+    let selections = if yes || answers.is_some() {
+        Some((0..defaults.len()).filter(|&i| defaults[i]).collect())
     } else {
-        return Ok(());
+        let theme: Box<dyn Theme> = if output::colors_enabled() {
+            Box::new(ColorfulTheme::default())
+        } else {
+            Box::new(SimpleTheme)
+        };
+        // indices into `data` of the episodes offered
+        let shown: Vec<usize> = if filter && reprs.len() > FILTER_THRESHOLD {
+            filter_episodes(&*theme, &reprs)?
+        } else {
+            (0..reprs.len()).collect()
+        };
+        MultiSelect::with_theme(&*theme)
+            .items(&shown.iter().map(|&i| &reprs[i]).collect::<Vec<_>>())
+            .defaults(&shown.iter().map(|&i| defaults[i]).collect::<Vec<_>>())
+            .max_length(120)
+            .interact_opt()?
+            .map(|s| s.into_iter().map(|i| shown[i]).collect::<Vec<_>>())
+    };
+    let mut selections = match selections {
+        Some(s) if s.is_empty() => {
+            output::warning("No episodes selected");
+            bail!(Exit(EXIT_NOTHING_SELECTED));
+        }
+        Some(s) => s,
+        None => bail!(Exit(EXIT_CANCELLED)),
     };
     selections.sort_unstable();
 
-    let mut reqs = Requirements::empty();
-    for v in ex.variables() {
-        match v {
-            "mal_id" => reqs |= Requirements::MAL_ID,
-            "anilist_id" => reqs |= Requirements::ANILIST_ID,
-            "title" => reqs |= Requirements::TITLE,
-            _ => (),
-        }
-        if reqs.is_all() {
-            break;
+    if remember {
+        if let Err(err) = selection::save(anime.anime_id, selections.iter().map(|&i| data[i].id)) {
+            output::warning(format_args!("{err:#}"));
         }
     }
-    if let Err(err) = anime.fetch_requirements(reqs) {
-        eprintln!("{err}");
+
+    // fetched right before the first execution, so nothing is spent on
+    // metadata when every episode fails or the executor does not use it
+    let mut reqs = Some(match &ex {
+        Some(ex) => Requirements::from_variables(ex.variables()),
+        None => Requirements::TITLE,
+    })
+    .filter(|r| !r.is_empty());
+
+    if let Some(dir) = directory.filter(|_| ex.is_some() && !print_command) {
+        std::fs::create_dir_all(&dir)
+            .and_then(|()| std::env::set_current_dir(&dir))
+            .with_context(|| format!("Cannot enter directory {}", dir.display()))?;
     }
 
+    let mut playlist = Vec::new();
+    let mut failed = 0;
     for (i, episode) in data.into_iter().enumerate() {
         if selections.is_empty() {
             break;
@@ -173,9 +1274,65 @@ fn _main() -> Result<()> {
             Err(_) => continue,
         }
 
-        let video = fetch_video_infos(episode.id)?;
+        // a broken embed only skips its own episode
+        let video = match client.fetch_video_infos(episode.id) {
+            Ok(video) => video,
+            Err(err) => {
+                output::error(format_args!("Episode {}: {err:#}", episode.number));
+                failed += 1;
+                continue;
+            }
+        };
+
+        if let Some(reqs) = reqs.take() {
+            if let Err(err) = client.fetch_requirements(&mut anime, reqs) {
+                output::warning(err);
+            }
+        }
+
+        match &ex {
+            Some(ex) if print_command => {
+                println!(
+                    "{}",
+                    ex.command_line(
+                        &EpisodeVariables::new(&anime, &video, &episode)
+                            .padded(padding)
+                            .seasons(seasons.as_ref())
+                    )?
+                );
+            }
+            Some(ex) => ex.execute(
+                &EpisodeVariables::new(&anime, &video, &episode)
+                    .padded(padding)
+                    .seasons(seasons.as_ref()),
+            )?,
+            None => {
+                let title = match anime.title.as_deref() {
+                    Some(title) => format!("{title} - {}", episode.label()),
+                    None => episode.label().into_owned(),
+                };
+                playlist.extend([
+                    "--{".to_string(),
+                    format!("--force-media-title={title}"),
+                    video.url.to_string(),
+                    "--}".to_string(),
+                ]);
+            }
+        }
+    }
+
+    if let Some(mpv) = mpv.filter(|_| !playlist.is_empty()) {
+        let status = std::process::Command::new(mpv)
+            .args(&playlist)
+            .status()
+            .context("Cannot run mpv")?;
+        if !status.success() {
+            bail!("mpv exited with {status}");
+        }
+    }
 
-        ex.execute(&EpisodeVariables::new(&anime, &video, &episode))?;
+    if failed > 0 {
+        bail!("{failed} episode(s) could not be resolved");
     }
 
     Ok(())
@@ -183,7 +1340,10 @@ fn _main() -> Result<()> {
 
 fn main() {
     if let Err(err) = _main() {
-        eprintln!("{}", err);
+        if let Some(Exit(code)) = err.downcast_ref() {
+            std::process::exit(*code);
+        }
+        output::error(format_args!("{err:#}"));
         std::process::exit(1);
     }
 }
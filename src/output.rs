@@ -0,0 +1,97 @@
+use std::{
+    fmt,
+    io::IsTerminal,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const GRAY: &str = "\x1b[90m";
+const RESET: &str = "\x1b[0m";
+const CLEAR_LINE: &str = "\r\x1b[K";
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Whether status messages are colored: stderr must be a terminal and
+/// `NO_COLOR` unset or empty.
+pub fn colors_enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+
+    *ENABLED.get_or_init(|| {
+        std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && std::io::stderr().is_terminal()
+    })
+}
+
+/// Whether debug messages are shown, enabled by a non-empty `AUDOWN_DEBUG`.
+pub fn debug_enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+
+    *ENABLED.get_or_init(|| std::env::var_os("AUDOWN_DEBUG").is_some_and(|v| !v.is_empty()))
+}
+
+fn print<T: fmt::Display>(color: &str, msg: T) {
+    if colors_enabled() {
+        eprintln!("{color}{msg}{RESET}");
+    } else {
+        eprintln!("{msg}");
+    }
+}
+
+pub fn success<T: fmt::Display>(msg: T) {
+    print(GREEN, msg)
+}
+
+pub fn warning<T: fmt::Display>(msg: T) {
+    print(YELLOW, msg)
+}
+
+pub fn error<T: fmt::Display>(msg: T) {
+    print(RED, msg)
+}
+
+pub fn debug<T: fmt::Display>(msg: T) {
+    if debug_enabled() {
+        print(GRAY, msg)
+    }
+}
+
+/// A status line redrawn in place on stderr, cleared on drop. Nothing is
+/// drawn when stderr is not a terminal.
+pub struct Progress {
+    last: Instant,
+    drawn: bool,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self {
+            last: Instant::now(),
+            drawn: false,
+        }
+    }
+
+    /// Replaces the line with `msg`, at most once per `PROGRESS_INTERVAL`.
+    pub fn update<T: fmt::Display>(&mut self, msg: T) {
+        if self.last.elapsed() < PROGRESS_INTERVAL || !std::io::stderr().is_terminal() {
+            return;
+        }
+        eprint!("{CLEAR_LINE}{msg}");
+        self.last = Instant::now();
+        self.drawn = true;
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if self.drawn {
+            eprint!("{CLEAR_LINE}");
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::Episode;
+
+/// Episode numbers selected by a comma separated list of `N`, `A-B`, `A-`
+/// and `-B`. Bounds can be decimal (`7-7.5`) and are inclusive, episodes are
+/// matched by [`Episode::number_f64`] so `7` does not match `7.5`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpisodeRanges(Vec<Range>);
+
+/// Inclusive bounds, open when `None`.
+pub(crate) type Range = (Option<f64>, Option<f64>);
+
+impl EpisodeRanges {
+    pub fn contains(&self, episode: &Episode) -> bool {
+        let Some(n) = episode.number_f64() else {
+            return false;
+        };
+        self.0.iter().any(|&range| in_range(range, n))
+    }
+}
+
+pub(crate) fn in_range((start, end): Range, n: f64) -> bool {
+    start.is_none_or(|start| n >= start) && end.is_none_or(|end| n <= end)
+}
+
+/// Parses a single `N`, `A-B`, `A-` or `-B`.
+pub(crate) fn parse_range(range: &str) -> Result<Range> {
+    let range = range.trim();
+    match range.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (bound(start)?, bound(end)?);
+            if let (Some(start), Some(end)) = (start, end) {
+                if start > end {
+                    bail!("Invalid episode range {range:?}");
+                }
+            }
+            Ok((start, end))
+        }
+        None => {
+            let n = bound(range)?.ok_or_else(|| anyhow!("Empty episode range"))?;
+            Ok((Some(n), Some(n)))
+        }
+    }
+}
+
+fn bound(s: &str) -> Result<Option<f64>> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(None);
+    }
+    match s.parse::<f64>() {
+        Ok(n) if n.is_finite() && n >= 0.0 => Ok(Some(n)),
+        _ => bail!("Invalid episode number {s:?}"),
+    }
+}
+
+impl FromStr for EpisodeRanges {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        s.split(',')
+            .map(|range| parse_range(range).with_context(|| format!("in {s:?}")))
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selected(ranges: &str) -> Vec<&'static str> {
+        let ranges: EpisodeRanges = ranges.parse().unwrap();
+        ["1", "2", "3", "7", "7.5", "8", "12a", "OVA"]
+            .into_iter()
+            .filter(|number| {
+                ranges.contains(&Episode {
+                    id: 0,
+                    number: number.to_string(),
+                    kind: None,
+                    airdate: None,
+                    duplicate: None,
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(selected("2"), ["2"]);
+        assert_eq!(selected("1-3"), ["1", "2", "3"]);
+        assert_eq!(selected("7"), ["7"]);
+        assert_eq!(selected("7.5"), ["7.5"]);
+        assert_eq!(selected("7-7.5"), ["7", "7.5"]);
+        assert_eq!(selected("7.5-8"), ["7.5", "8"]);
+        assert_eq!(selected("7.1-7.9"), ["7.5"]);
+        assert_eq!(selected("8-"), ["8", "12a"]);
+        assert_eq!(selected("-2, 12"), ["1", "2", "12a"]);
+        assert_eq!(selected("-"), ["1", "2", "3", "7", "7.5", "8", "12a"]);
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        for ranges in ["", "3-1", "7.5-7", "a", "1-b", "1--2", "1,,2", "inf", "NaN"] {
+            assert!(ranges.parse::<EpisodeRanges>().is_err(), "{ranges:?}");
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{de::Error as _, Deserialize};
+
+use crate::{
+    range::{in_range, parse_range, Range},
+    Episode,
+};
+
+/// Seasons of an anime the site numbers flatly, written as a comma separated
+/// list of an episode range and a season: `1-12 S1, 13-24 S2, 25- S3`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Seasons(Vec<(Range, u64)>);
+
+impl Seasons {
+    /// The season of `episode` and its number within it, counted from the
+    /// start of the range.
+    pub fn season_of(&self, episode: &Episode) -> Option<(u64, f64)> {
+        let n = episode.number_f64()?;
+        self.0
+            .iter()
+            .find(|&&(range, _)| in_range(range, n))
+            .map(|&((start, _), season)| (season, n - start.unwrap_or(1.0) + 1.0))
+    }
+}
+
+impl FromStr for Seasons {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        s.split(',')
+            .map(|entry| {
+                let entry = entry.trim();
+                let (range, season) = entry
+                    .rsplit_once(char::is_whitespace)
+                    .ok_or_else(|| anyhow!("Missing season in {entry:?}"))?;
+                let season = season
+                    .strip_prefix(['S', 's'])
+                    .and_then(|n| n.parse().ok())
+                    .ok_or_else(|| anyhow!("Invalid season {season:?}, expected S<number>"))?;
+                let range = parse_range(range).with_context(|| format!("in {entry:?}"))?;
+                Ok((range, season))
+            })
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Seasons {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(|e| D::Error::custom(format_args!("{e:#}")))
+    }
+}
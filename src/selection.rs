@@ -0,0 +1,48 @@
+use std::{collections::HashSet, fs, io::ErrorKind, path::PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::config;
+
+fn path(anime_id: u64) -> Result<PathBuf> {
+    let mut path = config::data_dir().ok_or_else(|| anyhow!("Cannot find data directory"))?;
+    path.push("selections");
+    path.push(anime_id.to_string());
+    Ok(path)
+}
+
+/// Ids of the episodes selected in the last run for `anime_id`, if saved.
+pub fn load(anime_id: u64) -> Result<Option<HashSet<u64>>> {
+    let path = path(anime_id)?;
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).context("Cannot read saved selection"),
+    };
+
+    Ok(Some(
+        content
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .collect(),
+    ))
+}
+
+pub fn save<I: IntoIterator<Item = u64>>(anime_id: u64, ids: I) -> Result<()> {
+    let path = path(anime_id)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("Cannot create data directory")?;
+    }
+
+    let content: String = ids.into_iter().map(|id| format!("{id}\n")).collect();
+    fs::write(&path, content).context("Cannot save selection")
+}
+
+pub fn forget(anime_id: u64) -> Result<()> {
+    match fs::remove_file(path(anime_id)?) {
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            Err(err).context("Cannot remove saved selection")
+        }
+        _ => Ok(()),
+    }
+}
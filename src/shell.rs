@@ -0,0 +1,112 @@
+use std::{borrow::Cow, fmt};
+
+use crate::template::Variables;
+
+/// Quotes `s` as a single word for the platform shell (`sh` on Unix, `cmd`
+/// on Windows).
+pub fn quote(s: &str) -> Cow<'_, str> {
+    if cfg!(windows) {
+        quote_cmd(s)
+    } else {
+        quote_sh(s)
+    }
+}
+
+fn is_plain(s: &str, safe: &[u8]) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|c| c.is_ascii_alphanumeric() || safe.contains(&c))
+}
+
+fn quote_sh(s: &str) -> Cow<'_, str> {
+    if is_plain(s, b"_-./:=@%+,") {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(format!("'{}'", s.replace('\'', r"'\''")))
+    }
+}
+
+// quoted for the program parsing its command line, then every character `cmd`
+// treats specially is escaped with `^`: `cmd` does not know about `\"`, and
+// expands `%VAR%` (and `!VAR!` with delayed expansion) even inside quotes
+fn quote_cmd(s: &str) -> Cow<'_, str> {
+    if is_plain(s, b"_-./:=@+,") {
+        return Cow::Borrowed(s);
+    }
+
+    let mut quoted = String::with_capacity(s.len() + 2);
+    let mut backslashes = 0;
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                backslashes = 0;
+            }
+        }
+        if c != '\\' {
+            quoted.push(c);
+        }
+    }
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+
+    let mut res = String::with_capacity(quoted.len() * 2);
+    for c in quoted.chars() {
+        if "()%!^\"<>&|".contains(c) {
+            res.push('^');
+        }
+        res.push(c);
+    }
+    Cow::Owned(res)
+}
+
+/// Variables whose values are shell-quoted when rendered, leaving the
+/// template's own text untouched.
+#[derive(Debug)]
+pub struct ShellQuoted<'a, V>(pub &'a V);
+
+#[derive(Debug)]
+pub struct QuotedValue<T>(T);
+
+impl<T: fmt::Display> fmt::Display for QuotedValue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&quote(&self.0.to_string()))
+    }
+}
+
+impl<'a, V: Variables> Variables for ShellQuoted<'a, V> {
+    type Item<'b> = QuotedValue<V::Item<'b>>
+    where
+        Self: 'b;
+
+    #[allow(clippy::needless_lifetimes)]
+    fn get<'b, S: AsRef<str>>(&'b self, name: S) -> Option<Self::Item<'b>> {
+        self.0.get(name).map(QuotedValue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sh() {
+        assert_eq!(quote_sh("a-b/c.mp4"), "a-b/c.mp4");
+        assert_eq!(quote_sh(""), "''");
+        assert_eq!(quote_sh("it's $HOME"), r"'it'\''s $HOME'");
+    }
+
+    #[test]
+    fn cmd() {
+        assert_eq!(quote_cmd("a-b/c.mp4"), "a-b/c.mp4");
+        assert_eq!(quote_cmd("a b"), r#"^"a b^""#);
+        assert_eq!(quote_cmd("100%PATH%!x!"), r#"^"100^%PATH^%^!x^!^""#);
+        assert_eq!(quote_cmd(r#"a"&calc&"\"#), r#"^"a\^"^&calc^&\^"\\^""#);
+    }
+}
@@ -0,0 +1,149 @@
+use std::sync::{PoisonError, RwLock};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    http::{self, HttpClient},
+    output,
+};
+
+pub const DEFAULT_DOMAIN: &str = "www.animeunity.so";
+
+static DOMAIN: RwLock<Option<Box<str>>> = RwLock::new(None);
+
+/// The domain currently used for site requests.
+pub fn domain() -> Box<str> {
+    DOMAIN
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+        .unwrap_or_else(|| DEFAULT_DOMAIN.into())
+}
+
+pub fn set_domain<S: Into<Box<str>>>(domain: S) {
+    *DOMAIN.write().unwrap_or_else(PoisonError::into_inner) = Some(domain.into());
+}
+
+pub fn url(path: &str) -> String {
+    format!("https://{}{path}", domain())
+}
+
+// the site alternates between the .so and .to TLDs
+fn alternate(domain: &str) -> Option<String> {
+    if let Some(name) = domain.strip_suffix(".so") {
+        Some(format!("{name}.to"))
+    } else {
+        domain.strip_suffix(".to").map(|name| format!("{name}.so"))
+    }
+}
+
+/// The current domain followed by its alternate TLD, if any.
+pub fn domains() -> Vec<String> {
+    let primary = domain();
+    let alternate = alternate(&primary);
+    std::iter::once(primary.into()).chain(alternate).collect()
+}
+
+/// GET `path` from the current domain, failing over to the alternate TLD
+/// when the domain cannot be reached, is blocked or answers with an empty
+/// page. The domain that answered is kept for the following requests. Other
+/// errors, like a 404 for a wrong id, are returned as they are.
+pub fn get(path: &str) -> Result<String> {
+    fetch(&http::Backend, path)
+}
+
+/// [`get`] through `http`.
+pub fn fetch(http: &dyn HttpClient, path: &str) -> Result<String> {
+    let primary = domain();
+
+    let err = match http.get(&format!("https://{primary}{path}")) {
+        Ok(body) if !body.trim().is_empty() => return Ok(body),
+        Ok(_) => anyhow!("Empty response from {primary}"),
+        Err(err) if is_unavailable(&err) => err,
+        Err(err) => return Err(err),
+    };
+
+    let Some(alternate) = alternate(&primary) else {
+        return Err(err);
+    };
+
+    match http.get(&format!("https://{alternate}{path}")) {
+        Ok(body) if !body.trim().is_empty() => {
+            output::warning(format_args!("{primary} is unreachable, using {alternate}"));
+            set_domain(alternate);
+            Ok(body)
+        }
+        _ => Err(err),
+    }
+}
+
+fn is_unavailable(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<crate::Error>(),
+        Some(crate::Error::CloudflareChallenge | crate::Error::RegionBlocked)
+    ) || http::is_network_error(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use super::*;
+
+    struct Fake {
+        pages: HashMap<&'static str, fn() -> Result<String>>,
+        requested: Mutex<Vec<String>>,
+    }
+
+    impl HttpClient for Fake {
+        fn get(&self, url: &str) -> Result<String> {
+            self.requested.lock().unwrap().push(url.to_string());
+            self.pages[url]()
+        }
+    }
+
+    #[test]
+    fn failover() {
+        set_domain("example.so");
+
+        let http = Fake {
+            pages: HashMap::from([
+                (
+                    "https://example.so/missing",
+                    (|| {
+                        Err(crate::Error::HttpStatus {
+                            status: 404,
+                            url: "https://example.so/missing".into(),
+                        }
+                        .into())
+                    }) as fn() -> _,
+                ),
+                ("https://example.to/missing", || Ok("page".to_string())),
+            ]),
+            requested: Mutex::default(),
+        };
+        let err = fetch(&http, "/missing").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(crate::Error::HttpStatus { status: 404, .. })
+        ));
+        assert_eq!(http.requested.lock().unwrap().len(), 1);
+        assert_eq!(&*domain(), "example.so");
+
+        let http = Fake {
+            pages: HashMap::from([
+                (
+                    "https://example.so/page",
+                    (|| Err(std::io::Error::from(std::io::ErrorKind::ConnectionRefused).into()))
+                        as fn() -> _,
+                ),
+                ("https://example.to/page", || Ok("page".to_string())),
+            ]),
+            requested: Mutex::default(),
+        };
+        assert_eq!(fetch(&http, "/page").unwrap(), "page");
+        assert_eq!(&*domain(), "example.to");
+
+        *DOMAIN.write().unwrap() = None;
+    }
+}
@@ -1,6 +1,7 @@
 use core::fmt;
 use std::{
-    collections::HashMap,
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
     iter::FusedIterator,
     ops::{RangeFrom, RangeTo},
 };
@@ -9,14 +10,16 @@ use nom::{
     branch::alt,
     bytes::complete::{tag, take_while, take_while1},
     character::complete::{char, one_of},
-    combinator::{all_consuming, map},
+    combinator::{all_consuming, map, verify},
     error::{ErrorKind, ParseError},
-    multi::{fold_many1, fold_many_m_n, many0},
+    multi::{fold_many0, fold_many1, fold_many_m_n, many0},
     sequence::{delimited, pair, preceded, terminated},
     AsChar, Compare, Err, ExtendInto, IResult, InputIter, InputLength, InputTake,
     InputTakeAtPosition, Offset, Slice,
 };
 
+use crate::Error;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 enum Item {
     Variable(Box<str>),
@@ -56,6 +59,91 @@ impl<'a, V: Variables> fmt::Display for BoundTemplate<'a, V> {
     }
 }
 
+/// Delegates to another [`Variables`], remembering every name it had no
+/// value for.
+#[derive(Debug)]
+pub struct Recording<'a, V> {
+    inner: &'a V,
+    missing: RefCell<BTreeSet<Box<str>>>,
+}
+
+impl<'a, V: Variables> Recording<'a, V> {
+    pub fn new(inner: &'a V) -> Self {
+        Self {
+            inner,
+            missing: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    /// Names looked up without a value so far, sorted.
+    pub fn missing(&self) -> Vec<Box<str>> {
+        self.missing.borrow().iter().cloned().collect()
+    }
+}
+
+impl<'a, V: Variables> Variables for Recording<'a, V> {
+    type Item<'b> = V::Item<'b>
+    where
+        Self: 'b;
+
+    #[allow(clippy::needless_lifetimes)]
+    fn get<'b, S: AsRef<str>>(&'b self, name: S) -> Option<Self::Item<'b>> {
+        let name = name.as_ref();
+        let value = self.inner.get(name);
+        if value.is_none() {
+            let mut missing = self.missing.borrow_mut();
+            if !missing.contains(name) {
+                missing.insert(name.into());
+            }
+        }
+        value
+    }
+}
+
+/// Another [`Variables`] with one more value.
+#[derive(Debug)]
+pub struct With<'a, V> {
+    inner: &'a V,
+    name: &'a str,
+    value: &'a str,
+}
+
+impl<'a, V> With<'a, V> {
+    pub fn new(inner: &'a V, name: &'a str, value: &'a str) -> Self {
+        Self { inner, name, value }
+    }
+}
+
+#[derive(Debug)]
+pub enum WithValue<'a, T> {
+    Inner(T),
+    Added(&'a str),
+}
+
+impl<'a, T: fmt::Display> fmt::Display for WithValue<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Inner(value) => value.fmt(f),
+            Self::Added(value) => f.write_str(value),
+        }
+    }
+}
+
+impl<'a, V: Variables> Variables for With<'a, V> {
+    type Item<'b> = WithValue<'b, V::Item<'b>>
+    where
+        Self: 'b;
+
+    #[allow(clippy::needless_lifetimes)]
+    fn get<'b, S: AsRef<str>>(&'b self, name: S) -> Option<Self::Item<'b>> {
+        if name.as_ref() == self.name {
+            Some(WithValue::Added(self.value))
+        } else {
+            self.inner.get(name).map(WithValue::Inner)
+        }
+    }
+}
+
 impl<T: fmt::Display> Variables for HashMap<Box<str>, T> {
     type Item<'a> = &'a T
     where
@@ -120,15 +208,58 @@ impl<'a> FusedIterator for VarIter<'a> {}
 
 impl Template {
     pub fn parse<S: AsRef<str>>(input: S) -> Option<Template> {
-        parser::<_, (_, ErrorKind)>(input.as_ref())
-            .ok()
-            .map(|(_, templ)| templ)
+        Self::try_parse(input).ok()
     }
 
-    pub fn variables(&self) -> VarIter {
+    /// Like [`Template::parse`], telling where and why parsing stopped.
+    pub fn try_parse<S: AsRef<str>>(input: S) -> Result<Template, Error> {
+        let input = input.as_ref();
+        let rest = match parser::<_, (_, ErrorKind)>(input) {
+            Ok((_, templ)) => return Ok(templ),
+            Err(Err::Error((rest, _)) | Err::Failure((rest, _))) => rest,
+            Err(Err::Incomplete(_)) => "",
+        };
+
+        let reason = if rest.starts_with("{}") {
+            "empty variable name, write `{{}}` for literal braces"
+        } else if rest.starts_with('{') && !rest.contains('}') {
+            "unclosed `{`"
+        } else if rest.starts_with('{') {
+            "invalid variable name"
+        } else if rest.starts_with('}') {
+            "unmatched `}`, write `}}` for a literal brace"
+        } else if rest.starts_with('\\') {
+            "invalid escape sequence"
+        } else if rest.starts_with('"') {
+            "unescaped `\"`"
+        } else {
+            "invalid template"
+        };
+        Err(Error::InvalidTemplate {
+            column: input[..input.len() - rest.len()].chars().count() + 1,
+            reason,
+        })
+    }
+
+    pub fn variables(&self) -> VarIter<'_> {
         VarIter(self.0.iter())
     }
 
+    /// Whether the template has no variables, rendering the same text
+    /// whatever the values.
+    pub fn is_constant(&self) -> bool {
+        self.variables().next().is_none()
+    }
+
+    /// The text of a template without variables, with escapes resolved.
+    pub fn as_literal(&self) -> Option<&str> {
+        match &*self.0 {
+            [] => Some(""),
+            [Item::Text(text)] => Some(text),
+            _ => None,
+        }
+    }
+
     pub fn bind<'a, T: Variables>(&'a self, vars: &'a T) -> BoundTemplate<'a, T> {
         BoundTemplate(self, vars)
     }
@@ -136,6 +267,35 @@ impl Template {
     pub fn render<T: Variables>(&self, vars: &T) -> Box<str> {
         self.bind(vars).to_string().into_boxed_str()
     }
+
+    /// Appends the rendered template to `buf`.
+    pub fn render_into<T: Variables>(&self, vars: &T, buf: &mut String) {
+        use fmt::Write;
+
+        let _ = write!(buf, "{}", self.bind(vars));
+    }
+
+    /// Renders every template against the same variables.
+    pub fn render_all<T: Variables>(templates: &[Template], vars: &T) -> Vec<String> {
+        templates
+            .iter()
+            .map(|t| {
+                let mut buf = String::with_capacity(t.text_len());
+                t.render_into(vars, &mut buf);
+                buf
+            })
+            .collect()
+    }
+
+    fn text_len(&self) -> usize {
+        self.0
+            .iter()
+            .map(|item| match item {
+                Item::Text(txt) => txt.len(),
+                Item::Variable(_) => 0,
+            })
+            .sum()
+    }
 }
 
 fn hexdigit<Input, Error>(input: Input) -> IResult<Input, u32, Error>
@@ -171,7 +331,7 @@ where
         6,
         terminated(hexdigit, take_while(|c: char| c == '_')),
         || 0,
-        |acc, n| acc * 10 + n,
+        |acc, n| acc * 16 + n,
     )(input)?;
 
     if let Some(c) = char::from_u32(n) {
@@ -242,11 +402,19 @@ where
                         map(char('a'), |_| S::C('\x07')),
                         map(char('"'), |_| S::C('"')),
                         map(char('\''), |_| S::C('\'')),
+                        // `\NNN`, three octal digits up to `\377`
+                        map(
+                            verify(
+                                fold_many_m_n(3, 3, octdigit, || 0, |acc, n| acc * 8 + n),
+                                |&n| n <= 0o377,
+                            ),
+                            |n| S::C(unsafe { char::from_u32_unchecked(n) }),
+                        ),
                         map(char('0'), |_| S::C('\0')),
                         preceded(
                             char('x'),
-                            map(pair(octdigit, hexdigit), |(a, b)| {
-                                S::C(unsafe { char::from_u32_unchecked(a * 10 + b) })
+                            map(pair(hexdigit, hexdigit), |(a, b)| {
+                                S::C(unsafe { char::from_u32_unchecked(a * 16 + b) })
                             }),
                         ),
                         preceded(
@@ -293,6 +461,53 @@ where
     )(input)
 }
 
+/// `{"name"}`, for names with characters the bare form does not allow.
+/// `\"` and `\\` stand for a quote and a backslash.
+fn quoted_variable<Input, Error>(input: Input) -> IResult<Input, Item, Error>
+where
+    Input: InputLength
+        + InputTake
+        + InputTakeAtPosition<Item = char>
+        + InputIter<Item = char>
+        + Compare<&'static str>
+        + Slice<RangeFrom<usize>>
+        + Slice<RangeTo<usize>>
+        + ExtendInto<Extender = String>
+        + Offset
+        + Clone,
+    Error: ParseError<Input>,
+{
+    map(
+        delimited(
+            tag("{\""),
+            verify(
+                fold_many0(
+                    alt((
+                        map(
+                            take_while1(|c: char| !matches!(c, '"' | '\\')),
+                            |t: Input| {
+                                let mut res = String::new();
+                                t.extend_into(&mut res);
+                                res
+                            },
+                        ),
+                        map(tag("\\\""), |_| "\"".to_string()),
+                        map(tag("\\\\"), |_| "\\".to_string()),
+                    )),
+                    String::new,
+                    |mut acc, part| {
+                        acc.push_str(&part);
+                        acc
+                    },
+                ),
+                |name: &str| !name.is_empty(),
+            ),
+            tag("\"}"),
+        ),
+        |name| Item::Variable(name.into_boxed_str()),
+    )(input)
+}
+
 fn parser<Input, Error>(input: Input) -> IResult<Input, Template, Error>
 where
     Input: InputLength
@@ -308,7 +523,102 @@ where
         + Clone,
     Error: ParseError<Input> + fmt::Debug,
 {
-    map(all_consuming(many0(alt((text, variable)))), |xs| {
-        Template(xs.into())
-    })(input)
+    map(
+        all_consuming(many0(alt((text, quoted_variable, variable)))),
+        |xs| Template(xs.into()),
+    )(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn literal(input: &str) -> String {
+        Template::try_parse(input)
+            .unwrap()
+            .as_literal()
+            .unwrap()
+            .to_string()
+    }
+
+    fn invalid_escape(column: usize) -> Error {
+        Error::InvalidTemplate {
+            column,
+            reason: "invalid escape sequence",
+        }
+    }
+
+    #[test]
+    fn hex_escapes() {
+        assert_eq!(literal(r"\x1b[0m"), "\x1b[0m");
+        assert_eq!(literal(r"\x7f"), "\x7f");
+        assert_eq!(literal(r"\x41\x4a\x4A"), "AJJ");
+        assert_eq!(literal(r"\x00\xff\xFF"), "\0\u{ff}\u{ff}");
+
+        for input in [r"\x", r"\x1", r"\xg1", r"\x1g"] {
+            assert_eq!(Template::try_parse(input), Err(invalid_escape(1)));
+        }
+        assert_eq!(Template::try_parse(r"ab\x7"), Err(invalid_escape(3)));
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        assert_eq!(literal(r"\u{1F600}"), "\u{1F600}");
+        assert_eq!(literal(r"\u{1f600}"), "\u{1f600}");
+        assert_eq!(literal(r"\u{e9}\u{20AC}"), "\u{e9}\u{20ac}");
+        assert_eq!(literal(r"\u{10_FFFF}"), "\u{10ffff}");
+
+        // surrogates and values past the last codepoint are not chars
+        for input in [
+            r"\u{D800}",
+            r"\u{110000}",
+            r"\u{}",
+            r"\u{1234567}",
+            r"\u1F600",
+        ] {
+            assert_eq!(Template::try_parse(input), Err(invalid_escape(1)));
+        }
+    }
+
+    #[test]
+    fn octal_escapes() {
+        // `\0` followed by two octal digits was a NUL and the digits before
+        // octal escapes were parsed
+        assert_eq!(literal(r"\012"), "\n");
+        assert_eq!(literal(r"\101\377"), "A\u{ff}");
+        assert_eq!(literal(r"\0"), "\0");
+        assert_eq!(literal(r"\01"), concat!("\0", "1"));
+        assert_eq!(literal(r"\08"), concat!("\0", "8"));
+        assert_eq!(literal(r"\0a"), "\0a");
+
+        for input in [r"\400", r"\777", r"\8"] {
+            assert_eq!(Template::try_parse(input), Err(invalid_escape(1)));
+        }
+    }
+
+    #[test]
+    fn quoted_variables() {
+        let vars = |input: &str| {
+            Template::try_parse(input)
+                .unwrap()
+                .variables()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vars(r#"{"weird key"}"#), ["weird key"]);
+        assert_eq!(vars(r#"{"{braces}"}"#), ["{braces}"]);
+        assert_eq!(vars(r#"{"say \"hi\" \\ bye"}"#), [r#"say "hi" \ bye"#]);
+        assert_eq!(vars(r#"a{"x y"}b{title}"#), ["x y", "title"]);
+        // the quoted form names the same variable as the bare one
+        assert_eq!(vars(r#"{"title"}"#), vars("{title}"));
+
+        let values = HashMap::from([("weird key", "1"), ("a\"b", "2")]);
+        let template = Template::try_parse(r#"[{"weird key"}] [{"a\"b"}]"#).unwrap();
+        assert_eq!(&*template.render(&values), "[1] [2]");
+
+        for input in [r#"{""}"#, r#"{"open}"#, r#"{"a\n"}"#, r#"{"a"b"}"#] {
+            assert!(Template::try_parse(input).is_err(), "{input}");
+        }
+    }
 }
@@ -0,0 +1,133 @@
+use std::fmt;
+
+use crate::{template::Variables, AnimeContext, Date, Episode, Seasons, Video};
+
+#[derive(Debug, Clone)]
+pub struct EpisodeVariables<'a> {
+    anime: &'a AnimeContext,
+    video: Option<&'a Video>,
+    episode: &'a Episode,
+    padding: usize,
+    seasons: Option<&'a Seasons>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum EpisodeValue<'a> {
+    Str(&'a str),
+    U64(u64),
+    /// A number written without decimals when it has none.
+    F64(f64),
+    Date(Date),
+    /// An episode number with its duplicate suffix.
+    Label(&'a str, &'a str),
+    /// An episode label padded with leading zeros to `width` characters.
+    Padded {
+        number: &'a str,
+        duplicate: Option<&'a str>,
+        width: usize,
+    },
+}
+
+impl<'a> fmt::Display for EpisodeValue<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EpisodeValue::Str(s) => fmt::Display::fmt(s, f),
+            EpisodeValue::U64(s) => fmt::Display::fmt(s, f),
+            EpisodeValue::F64(s) => fmt::Display::fmt(s, f),
+            EpisodeValue::Date(s) => fmt::Display::fmt(s, f),
+            EpisodeValue::Label(s, c) => write!(f, "{s}{c}"),
+            EpisodeValue::Padded {
+                number,
+                duplicate,
+                width,
+            } => {
+                write!(f, "{number:0>width$}")?;
+                match duplicate {
+                    Some(c) => write!(f, "{c}"),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl<'a> EpisodeVariables<'a> {
+    #[inline]
+    pub fn new(anime: &'a AnimeContext, video: &'a Video, episode: &'a Episode) -> Self {
+        Self {
+            anime,
+            video: Some(video),
+            episode,
+            padding: 0,
+            seasons: None,
+        }
+    }
+
+    /// Variables for an episode whose video has not been resolved: `file`
+    /// and `url` are unavailable.
+    #[inline]
+    pub fn unresolved(anime: &'a AnimeContext, episode: &'a Episode) -> Self {
+        Self {
+            anime,
+            video: None,
+            episode,
+            padding: 0,
+            seasons: None,
+        }
+    }
+
+    /// Sets the width of `{padded}`, usually [`InfoFetcher::padding`].
+    ///
+    /// [`InfoFetcher::padding`]: crate::InfoFetcher::padding
+    #[inline]
+    pub fn padded(mut self, width: usize) -> Self {
+        self.padding = width;
+        self
+    }
+
+    /// Provides `{season}` and `{episode_in_season}` from `seasons`.
+    #[inline]
+    pub fn seasons(mut self, seasons: Option<&'a Seasons>) -> Self {
+        self.seasons = seasons;
+        self
+    }
+}
+
+impl<'a> Variables for EpisodeVariables<'a> {
+    type Item<'b> = EpisodeValue<'b>
+    where
+        Self: 'b;
+
+    #[allow(clippy::needless_lifetimes)]
+    fn get<'b, S: AsRef<str>>(&'b self, name: S) -> Option<Self::Item<'b>> {
+        let name = name.as_ref();
+        match name {
+            "slug" => self.anime.slug.as_deref().map(EpisodeValue::Str),
+            "title" => self.anime.title.as_deref().map(EpisodeValue::Str),
+            "mal_id" => self.anime.mal_id.map(EpisodeValue::U64),
+            "anilist_id" => self.anime.anilist_id.map(EpisodeValue::U64),
+            "episode" => Some(match self.episode.duplicate.as_deref() {
+                Some(c) => EpisodeValue::Label(&self.episode.number, c),
+                None => EpisodeValue::Str(&self.episode.number),
+            }),
+            "number" => Some(EpisodeValue::Str(&self.episode.number)),
+            "padded" => Some(EpisodeValue::Padded {
+                number: &self.episode.number,
+                duplicate: self.episode.duplicate.as_deref(),
+                width: self.padding,
+            }),
+            "season" => self
+                .seasons?
+                .season_of(self.episode)
+                .map(|(season, _)| EpisodeValue::U64(season)),
+            "episode_in_season" => self
+                .seasons?
+                .season_of(self.episode)
+                .map(|(_, n)| EpisodeValue::F64(n)),
+            "airdate" => self.episode.airdate.map(EpisodeValue::Date),
+            "file" => self.video.map(|v| EpisodeValue::Str(&v.file)),
+            "url" => self.video.map(|v| EpisodeValue::Str(&v.url)),
+            _ => None,
+        }
+    }
+}